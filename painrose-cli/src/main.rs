@@ -1,16 +1,264 @@
-use painrose_lib::{geometry, language};
+use std::io::{Read, Write};
+
+use painrose_lib::analysis::{cfg, stack};
+use painrose_lib::bytecode;
+use painrose_lib::{geometry, language, minimize, translate};
+
+type Program = language::LanguageState<geometry::rhomb::RhombTiling>;
+
+/// Loads a program from a `.json` file or a source file, exiting on errors
+fn load(path: &str) -> Program {
+    let code = std::fs::read_to_string(path).unwrap();
+
+    if path.ends_with(".json") {
+        return match Program::new_from_json(&code) {
+            Ok(program) => program,
+            Err(error) => {
+                eprintln!("error: {error}");
+                std::process::exit(1);
+            }
+        };
+    }
+
+    let parsed = match Program::parse(
+        &code,
+        &language::parser::ParseOptions {
+            base_directory: std::path::Path::new(path).parent().map(Into::into),
+            ..Default::default()
+        },
+    ) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            eprint!("{}", error.render(&code));
+            std::process::exit(1);
+        }
+    };
+    for warning in &parsed.warnings {
+        eprintln!("warning: {warning}");
+    }
+    parsed.state
+}
+
+/// Describes where a tile comes from in the source, if it does
+fn describe_tile(
+    program: &Program,
+    tile: &geometry::tile_coordinate::TileCoordinate<geometry::rhomb::RhombTiling>,
+) -> String {
+    match program.get_source_map().get_location(tile) {
+        Some(location) => format!("line {}, column {}", location.line + 1, location.column + 1),
+        None => format!("tile `{tile}`"),
+    }
+}
+
+/// Prints the warnings of the static analysis
+fn lint(program: &Program) {
+    let graph = cfg::ControlFlowGraph::build(program, &cfg::AnalysisOptions::default());
+
+    for warning in stack::check_stack(program, &graph) {
+        match warning {
+            stack::StackWarning::Underflow {
+                tile,
+                character,
+                needed,
+                available,
+            } => println!(
+                "warning: `{character}` at {} takes {needed} items but the stack can have {available}",
+                describe_tile(program, &tile)
+            ),
+            stack::StackWarning::UnboundedGrowth { tiles } => println!(
+                "warning: the stack can grow forever in the loop starting at {}",
+                describe_tile(program, &tiles[0])
+            ),
+        }
+    }
+}
 
 fn main() {
     let args: Vec<_> = std::env::args().collect();
 
-    let code = std::fs::read_to_string(&args[1]).unwrap();
-    let mut program =
-        language::LanguageState::<geometry::rhomb::RhombTiling>::new_from_string(code).unwrap();
-    println!("{args:?}");
+    // `convert <file>` writes a source file as JSON and a JSON file as source
+    if args[1] == "convert" {
+        let program = load(&args[2]);
+        if args[2].ends_with(".json") {
+            print!("{}", program.serialize());
+        } else {
+            println!("{}", program.to_json());
+        }
+        return;
+    }
+
+    // `lint <file>` reports possible problems without running the program
+    if args[1] == "lint" {
+        lint(&load(&args[2]));
+        return;
+    }
+
+    // `bytecode <file>` lists the operations the program compiles to
+    if args[1] == "bytecode" {
+        let program = load(&args[2]);
+        print!(
+            "{}",
+            bytecode::Bytecode::compile(&program, &cfg::AnalysisOptions::default())
+        );
+        return;
+    }
+
+    // `differential <file>` runs the program with the interpreter and the bytecode VM on the
+    // same input, read from stdin, and reports where they disagree
+    if args[1] == "differential" {
+        let mut input = vec![];
+        std::io::stdin().read_to_end(&mut input).unwrap();
+        match bytecode::differential::compare(
+            load(&args[2]),
+            &input,
+            &bytecode::differential::DifferentialOptions::default(),
+        ) {
+            Some(divergence) => {
+                println!("The engines diverge: {divergence}");
+                std::process::exit(1);
+            }
+            None => println!("The engines agree"),
+        }
+        return;
+    }
+
+    // `brainfuck <file>` compiles a Brainfuck program and prints the Painrose source
+    if args[1] == "brainfuck" {
+        let source = std::fs::read_to_string(&args[2]).unwrap();
+        match translate::brainfuck::translate::<geometry::rhomb::RhombTiling>(
+            &source,
+            &translate::brainfuck::BrainfuckOptions::default(),
+        ) {
+            Ok(program) => print!("{}", program.serialize()),
+            Err(error) => {
+                eprintln!("error: {error}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // `befunge <file>` compiles a Befunge-93 program and prints the Painrose source
+    if args[1] == "befunge" {
+        let source = std::fs::read_to_string(&args[2]).unwrap();
+        match translate::befunge::translate::<geometry::rhomb::RhombTiling>(
+            &source,
+            &translate::befunge::BefungeOptions::default(),
+        ) {
+            Ok(program) => print!("{}", program.serialize()),
+            Err(error) => {
+                eprintln!("error: {error}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // `minimize <file> [input files ...]` drops the code that never runs and prints the rest,
+    // after checking the result does the same on every input, or on no input without any
+    if args[1] == "minimize" {
+        let program = load(&args[2]);
+        let mut inputs: Vec<_> = args[3..]
+            .iter()
+            .map(|path| std::fs::read(path).unwrap())
+            .collect();
+        if inputs.is_empty() {
+            inputs.push(vec![]);
+        }
+        let inputs: Vec<_> = inputs.iter().map(Vec::as_slice).collect();
+
+        match minimize::minimize(&program, &inputs, &minimize::MinimizeOptions::default()) {
+            Ok(program) => print!("{}", program.serialize()),
+            Err(mismatch) => {
+                eprintln!("error: {mismatch}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // `route <file> [start..end ...] [locality=N] [turn-cost=N]` places a program written on a
+    // single line, with loops over the given ranges of characters, and prints the source
+    if args[1] == "route" {
+        let code = std::fs::read_to_string(&args[2]).unwrap();
+        let mut loops = vec![];
+        let mut options = painrose_lib::layout::LayoutOptions::default();
+        for argument in &args[3..] {
+            let parsed = match argument.split_once('=') {
+                Some(("locality", value)) => {
+                    value.parse().map(|value| options.locality = value).ok()
+                }
+                Some(("turn-cost", value)) => {
+                    value.parse().map(|value| options.turn_cost = value).ok()
+                }
+                _ => argument.split_once("..").and_then(|(start, end)| {
+                    loops.push(start.parse().ok()?..end.parse().ok()?);
+                    Some(())
+                }),
+            };
+            if parsed.is_none() {
+                eprintln!("error: `{argument}` is not a loop like `3..10` or an option");
+                std::process::exit(1);
+            }
+        }
+
+        match painrose_lib::layout::lay_out::<geometry::rhomb::RhombTiling>(
+            code.trim_end_matches(['\n', '\r']),
+            &loops,
+            &options,
+        ) {
+            Ok(program) => print!("{}", program.serialize()),
+            Err(error) => {
+                eprintln!("error: {error}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // `<file> [--detect-loops]` runs the program, optionally stopping it when it can never finish
+    let mut program = load(&args[1]);
 
     program.draw().unwrap();
+    program.compile();
+    if args[2..]
+        .iter()
+        .any(|argument| argument == "--detect-loops")
+    {
+        program.set_loop_detection(Some(language::loop_detection::LoopDetection::default()));
+    }
 
     while program.is_running() {
         program.step(&mut std::io::stdout(), &mut std::io::stdin());
     }
+    std::io::stdout().flush().unwrap();
+
+    let outcome = program.get_outcome().unwrap();
+    match outcome {
+        language::RunOutcome::RuntimeError(error) => match program
+            .get_source_map()
+            .get_location(program.get_instruction_pointer())
+        {
+            Some(location) => eprintln!(
+                "Runtime error: {error} at line {}, column {}",
+                location.line + 1,
+                location.column + 1
+            ),
+            None => eprintln!("Runtime error: {error}"),
+        },
+        language::RunOutcome::InfiniteLoop { length } => {
+            let tiles = program.get_loop_tiles();
+            eprintln!(
+                "Infinite loop: the program repeats the same {length} steps over {} tiles forever, starting at {}",
+                tiles.len(),
+                describe_tile(&program, &tiles[0])
+            );
+        }
+        language::RunOutcome::LeftProgram => eprintln!(
+            "Left the program: the instruction pointer walked away from the code at {}",
+            describe_tile(&program, program.get_instruction_pointer())
+        ),
+        _ => (),
+    }
+    std::process::exit(outcome.exit_code());
 }
@@ -0,0 +1,401 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::geometry::tile_coordinate::TileCoordinate;
+use crate::geometry::tiling::Tiling;
+use crate::language::instructions::Instruction;
+use crate::language::{literals, FollowableDirection, LanguageState};
+
+/// The index of a block in [`ControlFlowGraph::blocks`]
+pub type BlockId = usize;
+
+#[derive(Clone, Debug)]
+pub struct AnalysisOptions {
+    /// How many empty tiles in a row the instruction pointer can cross before it is considered to
+    /// have left the program
+    pub max_empty_steps: usize,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        AnalysisOptions {
+            max_empty_steps: 32,
+        }
+    }
+}
+
+/// The mode of the interpreter, without the values it collected so far
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum StaticMode {
+    Normal,
+    CharString,
+    ArrayString,
+    Char,
+    Number {
+        has_point: bool,
+    },
+    /// An escape sequence inside the given mode, with the characters read so far
+    Escape(Box<StaticMode>, String),
+}
+
+/// A position of the instruction pointer. The direction is the one it continues in after
+/// evaluating the tile, unless the tile turns it.
+pub struct ProgramState<T: Tiling> {
+    pub tile: TileCoordinate<T>,
+    pub direction: T::Edge,
+    pub mode: StaticMode,
+}
+
+impl<T: Tiling> std::fmt::Debug for ProgramState<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProgramState")
+            .field("tile", &self.tile)
+            .field("direction", &self.direction)
+            .field("mode", &self.mode)
+            .finish()
+    }
+}
+
+impl<T: Tiling> Clone for ProgramState<T> {
+    fn clone(&self) -> Self {
+        ProgramState {
+            tile: self.tile.clone(),
+            direction: self.direction,
+            mode: self.mode.clone(),
+        }
+    }
+}
+
+impl<T: Tiling> PartialEq for ProgramState<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.tile == other.tile && self.direction == other.direction && self.mode == other.mode
+    }
+}
+
+impl<T: Tiling> Eq for ProgramState<T> {}
+
+impl<T: Tiling> std::hash::Hash for ProgramState<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.tile.hash(state);
+        self.direction.hash(state);
+        self.mode.hash(state);
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Terminator {
+    /// Continues with the start of another block
+    Jump(BlockId),
+    /// A `^` or `v`, continuing straight when the popped value is falsy
+    Branch { straight: BlockId, turned: BlockId },
+    /// A `?`, the handler is where the instruction pointer goes after a runtime error
+    Guard { next: BlockId, handler: BlockId },
+    /// A `;` or `Q`
+    Stop,
+    /// An error that is always raised, like an invalid escape sequence
+    RuntimeError,
+    /// The instruction pointer crossed too many empty tiles in a row
+    LeftProgram,
+}
+
+impl Terminator {
+    pub fn successors(self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(next) => vec![next],
+            Terminator::Branch { straight, turned } => vec![straight, turned],
+            Terminator::Guard { next, handler } => vec![next, handler],
+            Terminator::Stop | Terminator::RuntimeError | Terminator::LeftProgram => vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BasicBlock<T: Tiling> {
+    /// The states in the order they are visited, the last one decides the terminator
+    pub states: Vec<ProgramState<T>>,
+    pub terminator: Terminator,
+}
+
+#[derive(Clone, Debug)]
+pub struct ControlFlowGraph<T: Tiling> {
+    pub blocks: Vec<BasicBlock<T>>,
+    pub entry: BlockId,
+}
+
+/// A state during exploration, empty tiles in a row are counted so paths that leave the program
+/// end
+type Key<T> = (ProgramState<T>, usize);
+
+enum Flow<T: Tiling> {
+    Straight(Key<T>),
+    Branch(Key<T>, Key<T>),
+    Guard(Key<T>, Key<T>),
+    Stop,
+    RuntimeError,
+    LeftProgram,
+}
+
+impl<T: Tiling> Flow<T> {
+    fn successors(&self) -> Vec<&Key<T>> {
+        match self {
+            Flow::Straight(next) => vec![next],
+            Flow::Branch(a, b) | Flow::Guard(a, b) => vec![a, b],
+            Flow::Stop | Flow::RuntimeError | Flow::LeftProgram => vec![],
+        }
+    }
+}
+
+struct Explorer<'a, T: Tiling>
+where
+    T::Edge: FollowableDirection,
+{
+    program: &'a LanguageState<T>,
+    options: &'a AnalysisOptions,
+}
+
+impl<'a, T: Tiling> Explorer<'a, T>
+where
+    T::Edge: FollowableDirection,
+{
+    /// Moves from the tile of `key` in `direction`, `None` if that leaves the program
+    fn go(&self, key: &Key<T>, direction: T::Edge, mode: StaticMode) -> Option<Key<T>> {
+        let (state, empty_steps) = key;
+        let empty_steps = match self.program.get_char(&state.tile) {
+            Some(_) => 0,
+            None => empty_steps + 1,
+        };
+        if empty_steps > self.options.max_empty_steps {
+            return None;
+        }
+
+        let (tile, entry) = state.tile.go(direction).ok()?;
+        Some((
+            ProgramState {
+                tile,
+                direction: entry.opposite(),
+                mode,
+            },
+            empty_steps,
+        ))
+    }
+
+    fn straight(&self, key: &Key<T>, direction: T::Edge, mode: StaticMode) -> Flow<T> {
+        match self.go(key, direction, mode) {
+            Some(next) => Flow::Straight(next),
+            None => Flow::LeftProgram,
+        }
+    }
+
+    /// Mirrors [`LanguageState::step`] for the parts that don't depend on the stack
+    fn flow(&self, key: &Key<T>) -> Flow<T> {
+        let state = &key.0;
+        let character = self.program.get_char(&state.tile);
+        let instruction = character.and_then(Instruction::from_char);
+        let direction = state.direction;
+
+        match &state.mode {
+            StaticMode::Normal => self.evaluate(key, instruction),
+            StaticMode::Number { has_point } => match character {
+                Some(c)
+                    if literals::is_number_literal_character(
+                        if *has_point { "." } else { "" },
+                        c,
+                    ) =>
+                {
+                    self.straight(
+                        key,
+                        direction,
+                        StaticMode::Number {
+                            has_point: *has_point || c == '.',
+                        },
+                    )
+                }
+                _ => self.evaluate(key, instruction),
+            },
+            StaticMode::Escape(inner, sequence) => {
+                let mut sequence = sequence.clone();
+                sequence.push(character.unwrap_or('\0'));
+                match literals::resolve_escape(&sequence) {
+                    Ok(Some(_)) => {
+                        let mode = match inner.as_ref() {
+                            StaticMode::Char => StaticMode::Normal,
+                            inner => inner.clone(),
+                        };
+                        self.straight(key, direction, mode)
+                    }
+                    Ok(None) => {
+                        self.straight(key, direction, StaticMode::Escape(inner.clone(), sequence))
+                    }
+                    Err(_) => Flow::RuntimeError,
+                }
+            }
+            mode @ (StaticMode::Char | StaticMode::CharString | StaticMode::ArrayString)
+                if character == Some('\\') =>
+            {
+                self.straight(
+                    key,
+                    direction,
+                    StaticMode::Escape(Box::new(mode.clone()), String::new()),
+                )
+            }
+            StaticMode::Char => self.straight(key, direction, StaticMode::Normal),
+            StaticMode::ArrayString => match instruction {
+                Some(Instruction::StartArrayString) => {
+                    self.straight(key, direction, StaticMode::Normal)
+                }
+                _ => self.straight(key, direction, StaticMode::ArrayString),
+            },
+            StaticMode::CharString => match instruction {
+                Some(Instruction::StartCharacterString) => {
+                    self.straight(key, direction, StaticMode::Normal)
+                }
+                _ => self.straight(key, direction, StaticMode::CharString),
+            },
+        }
+    }
+
+    fn evaluate(&self, key: &Key<T>, instruction: Option<Instruction>) -> Flow<T> {
+        let direction = key.0.direction;
+        let normal = |direction| self.go(key, direction, StaticMode::Normal);
+
+        let Some(instruction) = instruction else {
+            return self.straight(key, direction, StaticMode::Normal);
+        };
+
+        if instruction.is_nonconditional_movement_instruction() {
+            let direction = match instruction {
+                Instruction::TurnLeft => direction.turn_left(),
+                Instruction::TurnRight => direction.turn_right(),
+                _ => direction.opposite(),
+            };
+            return self.straight(key, direction, StaticMode::Normal);
+        }
+
+        let (straight, turned) = match instruction {
+            Instruction::TurnLeftIf => (normal(direction), normal(direction.turn_left())),
+            Instruction::TurnRightIf | Instruction::Guard => {
+                (normal(direction), normal(direction.turn_right()))
+            }
+            Instruction::Quit | Instruction::Exit => return Flow::Stop,
+            Instruction::StartCharacterString => {
+                return self.straight(key, direction, StaticMode::CharString)
+            }
+            Instruction::StartArrayString => {
+                return self.straight(key, direction, StaticMode::ArrayString)
+            }
+            Instruction::StartCharacter => return self.straight(key, direction, StaticMode::Char),
+            Instruction::StartNumber => {
+                return self.straight(key, direction, StaticMode::Number { has_point: false })
+            }
+            _ => return self.straight(key, direction, StaticMode::Normal),
+        };
+
+        // A branch that leaves the program still counts as a branch, it just can't be followed
+        match (straight, turned) {
+            (Some(straight), Some(turned)) if instruction == Instruction::Guard => {
+                Flow::Guard(straight, turned)
+            }
+            (Some(straight), Some(turned)) => Flow::Branch(straight, turned),
+            (Some(next), None) | (None, Some(next)) => Flow::Straight(next),
+            (None, None) => Flow::LeftProgram,
+        }
+    }
+}
+
+impl<T: Tiling> ControlFlowGraph<T>
+where
+    T::Edge: FollowableDirection,
+{
+    /// Explores every state reachable from the entry of the program, wherever it is running now
+    pub fn build(program: &LanguageState<T>, options: &AnalysisOptions) -> Self {
+        let explorer = Explorer { program, options };
+
+        let (tile, direction) = program.get_entry();
+        let entry: Key<T> = (
+            ProgramState {
+                tile: tile.clone(),
+                direction,
+                mode: StaticMode::Normal,
+            },
+            0,
+        );
+
+        let mut flows: HashMap<Key<T>, Flow<T>> = HashMap::new();
+        let mut predecessors: HashMap<Key<T>, usize> = HashMap::new();
+        let mut decision_targets: HashSet<Key<T>> = HashSet::new();
+        let mut queue = VecDeque::from([entry.clone()]);
+        while let Some(key) = queue.pop_front() {
+            if flows.contains_key(&key) {
+                continue;
+            }
+            let flow = explorer.flow(&key);
+            if let Flow::Branch(a, b) | Flow::Guard(a, b) = &flow {
+                decision_targets.extend([a.clone(), b.clone()]);
+            }
+            for next in flow.successors() {
+                *predecessors.entry(next.clone()).or_default() += 1;
+                queue.push_back(next.clone());
+            }
+            flows.insert(key, flow);
+        }
+
+        // Blocks start at the entry, after every decision and wherever paths merge
+        let is_leader =
+            |key: &Key<T>| *key == entry || predecessors[key] > 1 || decision_targets.contains(key);
+
+        let mut block_ids: HashMap<Key<T>, BlockId> = HashMap::from([(entry.clone(), 0)]);
+        let mut queue = VecDeque::from([entry.clone()]);
+        let mut get_block_id = |key: &Key<T>, queue: &mut VecDeque<Key<T>>| {
+            let next_id = block_ids.len();
+            *block_ids.entry(key.clone()).or_insert_with(|| {
+                queue.push_back(key.clone());
+                next_id
+            })
+        };
+
+        let mut blocks = vec![];
+        while let Some(leader) = queue.pop_front() {
+            let mut states = vec![];
+            let mut key = leader;
+            let terminator = loop {
+                states.push(key.0.clone());
+                match &flows[&key] {
+                    Flow::Straight(next) if is_leader(next) => {
+                        break Terminator::Jump(get_block_id(next, &mut queue));
+                    }
+                    Flow::Straight(next) => key = next.clone(),
+                    Flow::Branch(straight, turned) => {
+                        break Terminator::Branch {
+                            straight: get_block_id(straight, &mut queue),
+                            turned: get_block_id(turned, &mut queue),
+                        }
+                    }
+                    Flow::Guard(next, handler) => {
+                        break Terminator::Guard {
+                            next: get_block_id(next, &mut queue),
+                            handler: get_block_id(handler, &mut queue),
+                        }
+                    }
+                    Flow::Stop => break Terminator::Stop,
+                    Flow::RuntimeError => break Terminator::RuntimeError,
+                    Flow::LeftProgram => break Terminator::LeftProgram,
+                }
+            };
+            blocks.push(BasicBlock { states, terminator });
+        }
+
+        ControlFlowGraph { blocks, entry: 0 }
+    }
+
+    /// The blocks that can continue after the given block
+    pub fn successors(&self, block: BlockId) -> Vec<BlockId> {
+        self.blocks[block].terminator.successors()
+    }
+
+    /// Every tile the instruction pointer can reach
+    pub fn reachable_tiles(&self) -> HashSet<&TileCoordinate<T>> {
+        self.blocks
+            .iter()
+            .flat_map(|block| block.states.iter().map(|state| &state.tile))
+            .collect()
+    }
+}
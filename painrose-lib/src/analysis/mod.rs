@@ -0,0 +1,3 @@
+//! Static analysis of programs, without running them
+pub mod cfg;
+pub mod stack;
@@ -0,0 +1,274 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::geometry::tile_coordinate::TileCoordinate;
+use crate::geometry::tiling::Tiling;
+use crate::language::instructions::{Instruction, StackEffect};
+use crate::language::{literals, FollowableDirection, LanguageState};
+
+use super::cfg::{BlockId, ControlFlowGraph, ProgramState, StaticMode, Terminator};
+
+/// How often the stack of a block can grow before it is considered to grow forever
+const WIDENING_THRESHOLD: usize = 3;
+
+#[derive(Debug)]
+pub enum StackWarning<T: Tiling> {
+    /// The instruction can run with fewer items on the stack than it uses, the missing items
+    /// will be zero
+    Underflow {
+        tile: TileCoordinate<T>,
+        character: char,
+        needed: usize,
+        available: usize,
+    },
+    /// The stack can grow every time the instruction pointer goes around a loop over these tiles
+    UnboundedGrowth { tiles: Vec<TileCoordinate<T>> },
+}
+
+/// The possible depths of the current stack, `max` is `None` when it is unbounded
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Depth {
+    min: usize,
+    max: Option<usize>,
+}
+
+impl Depth {
+    fn join(self, other: Depth) -> Depth {
+        Depth {
+            min: self.min.min(other.min),
+            max: self.max.zip(other.max).map(|(a, b)| a.max(b)),
+        }
+    }
+
+    fn apply(self, effect: StackEffect) -> Depth {
+        Depth {
+            min: self.min.saturating_sub(effect.pops) + effect.pushes,
+            max: self
+                .max
+                .map(|max| max.saturating_sub(effect.pops) + effect.pushes),
+        }
+    }
+}
+
+/// What a state does to the current stack. Strings, characters and numbers are pushed as soon as
+/// they start or as each character is read, no instruction runs in between so the depth is the
+/// same once they end.
+enum Effect {
+    Fixed(StackEffect),
+    /// Pushes nothing or all of `pushes`, `[` and `]` only push their result on arrays
+    MaybePushes(StackEffect),
+    /// `B` starts a new empty stack
+    NewStack,
+    Unknown,
+}
+
+fn get_effect<T: Tiling>(program: &LanguageState<T>, state: &ProgramState<T>) -> Effect
+where
+    T::Edge: FollowableDirection,
+{
+    let character = program.get_char(&state.tile);
+    let instruction = character.and_then(Instruction::from_char);
+    let push = |pushes| Effect::Fixed(StackEffect { pops: 0, pushes });
+
+    let evaluate = || match instruction {
+        Some(
+            Instruction::StartArrayString | Instruction::StartCharacter | Instruction::StartNumber,
+        ) => push(1),
+        Some(Instruction::PushStack) => Effect::NewStack,
+        Some(instruction @ (Instruction::GetArrayN | Instruction::PutArrayN)) => instruction
+            .stack_effect()
+            .map_or(Effect::Unknown, Effect::MaybePushes),
+        Some(instruction) => instruction
+            .stack_effect()
+            .map_or(Effect::Unknown, Effect::Fixed),
+        None => push(0),
+    };
+
+    match &state.mode {
+        StaticMode::Normal => evaluate(),
+        StaticMode::Number { has_point } => match character {
+            Some(c)
+                if literals::is_number_literal_character(if *has_point { "." } else { "" }, c) =>
+            {
+                push(0)
+            }
+            _ => evaluate(),
+        },
+        StaticMode::Escape(inner, sequence) => {
+            let mut sequence = sequence.clone();
+            sequence.push(character.unwrap_or('\0'));
+            match (inner.as_ref(), literals::resolve_escape(&sequence)) {
+                (StaticMode::CharString, Ok(Some(_))) => push(1),
+                _ => push(0),
+            }
+        }
+        StaticMode::CharString => match (character, instruction) {
+            (Some('\\'), _) | (_, Some(Instruction::StartCharacterString)) => push(0),
+            _ => push(1),
+        },
+        StaticMode::ArrayString | StaticMode::Char => push(0),
+    }
+}
+
+/// The blocks that can reach `block` and be reached from it
+fn get_loop<T: Tiling>(graph: &ControlFlowGraph<T>, block: BlockId) -> HashSet<BlockId>
+where
+    T::Edge: FollowableDirection,
+{
+    let reachable_from = |start: BlockId| {
+        let mut seen = HashSet::new();
+        let mut stack = graph.successors(start);
+        while let Some(next) = stack.pop() {
+            if seen.insert(next) {
+                stack.extend(graph.successors(next));
+            }
+        }
+        seen
+    };
+
+    reachable_from(block)
+        .into_iter()
+        .filter(|&other| reachable_from(other).contains(&block))
+        .collect()
+}
+
+/// Follows the depth of the current stack over every path of the graph, reporting instructions
+/// that can run on a stack that is too small and loops that grow the stack without bound
+pub fn check_stack<T: Tiling>(
+    program: &LanguageState<T>,
+    graph: &ControlFlowGraph<T>,
+) -> Vec<StackWarning<T>>
+where
+    T::Edge: FollowableDirection,
+{
+    // `None` means the depth is unknown, after instructions with an effect that depends on values
+    let mut entry_depths: HashMap<BlockId, Option<Depth>> = HashMap::new();
+    let mut updates: HashMap<BlockId, usize> = HashMap::new();
+    let mut growing = vec![];
+
+    let run_block = |block: BlockId, depth: Option<Depth>, warnings: &mut Vec<StackWarning<T>>| {
+        graph.blocks[block]
+            .states
+            .iter()
+            .fold(depth, |depth, state| {
+                let depth = depth?;
+                let (effect, may_skip_pushes) = match get_effect(program, state) {
+                    Effect::Fixed(effect) => (effect, false),
+                    Effect::MaybePushes(effect) => (effect, true),
+                    Effect::NewStack => {
+                        return Some(Depth {
+                            min: 0,
+                            max: Some(0),
+                        })
+                    }
+                    Effect::Unknown => return None,
+                };
+                if depth.min < effect.pops {
+                    warnings.push(StackWarning::Underflow {
+                        tile: state.tile.clone(),
+                        character: program.get_char(&state.tile).unwrap_or(' '),
+                        needed: effect.pops,
+                        available: depth.min,
+                    });
+                }
+                let pushed = depth.apply(effect);
+                Some(if may_skip_pushes {
+                    pushed.join(depth.apply(StackEffect {
+                        pushes: 0,
+                        ..effect
+                    }))
+                } else {
+                    pushed
+                })
+            })
+    };
+
+    let mut queue = VecDeque::from([(
+        graph.entry,
+        Some(Depth {
+            min: 0,
+            max: Some(0),
+        }),
+    )]);
+    while let Some((block, depth)) = queue.pop_front() {
+        let old = entry_depths.get(&block).copied();
+        let mut depth = match old {
+            Some(old) => {
+                let joined = old.zip(depth).map(|(old, new)| old.join(new));
+                if joined == old {
+                    continue;
+                }
+                joined
+            }
+            None => depth,
+        };
+
+        // Only a growing `max` means the stack can grow forever, a dropping `min` stops at 0
+        let update_count = updates.entry(block).or_default();
+        *update_count += 1;
+        if let (Some(depth), Some(Some(old))) = (&mut depth, old) {
+            if *update_count > WIDENING_THRESHOLD {
+                if depth.min < old.min {
+                    depth.min = 0;
+                }
+                if depth.max.is_some() && depth.max != old.max {
+                    depth.max = None;
+                    growing.push(block);
+                }
+            }
+        }
+        entry_depths.insert(block, depth);
+
+        let exit_depth = run_block(block, depth, &mut vec![]);
+        match graph.blocks[block].terminator {
+            // The handler gets the error code on top of whatever was on the stack at the error
+            Terminator::Guard { next, handler } => {
+                queue.push_back((next, exit_depth));
+                queue.push_back((handler, None));
+            }
+            terminator => queue.extend(
+                terminator
+                    .successors()
+                    .into_iter()
+                    .map(|next| (next, exit_depth)),
+            ),
+        }
+    }
+
+    // A tile can be part of several blocks, for example when it is reached in different modes
+    let mut warnings: Vec<StackWarning<T>> = vec![];
+    let mut blocks: Vec<_> = entry_depths.into_iter().collect();
+    blocks.sort_by_key(|(block, _)| *block);
+    for (block, depth) in blocks {
+        let mut block_warnings = vec![];
+        run_block(block, depth, &mut block_warnings);
+        for warning in block_warnings {
+            let StackWarning::Underflow { tile, .. } = &warning else {
+                continue;
+            };
+            if !warnings.iter().any(
+                |other| matches!(other, StackWarning::Underflow { tile: other, .. } if other == tile),
+            ) {
+                warnings.push(warning);
+            }
+        }
+    }
+
+    let mut loops = vec![];
+    for block in growing {
+        let mut tiles = vec![];
+        let mut blocks: Vec<_> = get_loop(graph, block).into_iter().collect();
+        blocks.sort();
+        if loops.contains(&blocks) {
+            continue;
+        }
+        for state in blocks.iter().flat_map(|&block| &graph.blocks[block].states) {
+            if !tiles.contains(&state.tile) {
+                tiles.push(state.tile.clone());
+            }
+        }
+        loops.push(blocks);
+        warnings.push(StackWarning::UnboundedGrowth { tiles });
+    }
+
+    warnings
+}
@@ -0,0 +1,163 @@
+use crate::analysis::cfg::{
+    AnalysisOptions, ControlFlowGraph, ProgramState, StaticMode, Terminator,
+};
+use crate::geometry::tile_coordinate::TileCoordinate;
+use crate::geometry::tiling::Tiling;
+use crate::language::instructions::Instruction;
+use crate::language::{literals, FollowableDirection, LanguageState};
+
+use super::{Bytecode, Op};
+
+struct Emitter<'a, T: Tiling>
+where
+    T::Edge: FollowableDirection,
+{
+    program: &'a LanguageState<T>,
+    ops: Vec<Op>,
+    tiles: Vec<TileCoordinate<T>>,
+}
+
+impl<T: Tiling> Emitter<'_, T>
+where
+    T::Edge: FollowableDirection,
+{
+    fn emit(&mut self, op: Op, tile: &TileCoordinate<T>) {
+        self.ops.push(op);
+        self.tiles.push(tile.clone());
+    }
+
+    /// Mirrors [`LanguageState::step`], leaving out everything that moves the instruction
+    /// pointer as that is handled by the terminators of the blocks
+    fn state(&mut self, state: &ProgramState<T>) {
+        let tile = &state.tile;
+        let character = self.program.get_char(tile);
+        let instruction = character.and_then(Instruction::from_char);
+        let character_code = character.map_or(0.0, |c| c as u32 as f64);
+
+        match &state.mode {
+            StaticMode::Normal => self.instruction(instruction, tile),
+            StaticMode::Number { has_point } => match character {
+                Some(c)
+                    if literals::is_number_literal_character(
+                        if *has_point { "." } else { "" },
+                        c,
+                    ) =>
+                {
+                    self.emit(Op::Digit(c), tile)
+                }
+                _ => {
+                    self.emit(Op::EndNumber, tile);
+                    self.instruction(instruction, tile);
+                }
+            },
+            StaticMode::Escape(inner, sequence) => {
+                let mut sequence = sequence.clone();
+                sequence.push(character.unwrap_or('\0'));
+                match literals::resolve_escape(&sequence) {
+                    Ok(Some(c)) if **inner == StaticMode::Char => {
+                        self.emit(Op::Push(c as u32 as f64), tile)
+                    }
+                    Ok(Some(c)) => self.emit(Op::Character(c as u32 as f64), tile),
+                    Ok(None) => {}
+                    Err(error) => self.emit(Op::Error(error), tile),
+                }
+            }
+            StaticMode::Char | StaticMode::CharString | StaticMode::ArrayString
+                if character == Some('\\') => {}
+            StaticMode::Char => self.emit(Op::Push(character_code), tile),
+            StaticMode::ArrayString => match instruction {
+                Some(Instruction::StartArrayString) => self.emit(Op::EndArrayString, tile),
+                _ => self.emit(Op::Character(character_code), tile),
+            },
+            StaticMode::CharString => match instruction {
+                Some(Instruction::StartCharacterString) => self.emit(Op::EndCharacterString, tile),
+                _ => self.emit(Op::Character(character_code), tile),
+            },
+        }
+    }
+
+    /// An instruction evaluated in normal mode
+    fn instruction(&mut self, instruction: Option<Instruction>, tile: &TileCoordinate<T>) {
+        match instruction {
+            None | Some(Instruction::StartCharacter) => {}
+            Some(instruction) if instruction.is_nonconditional_movement_instruction() => {}
+            // Compiled by the terminator of the block
+            Some(Instruction::TurnLeftIf | Instruction::TurnRightIf | Instruction::Guard) => {}
+            Some(Instruction::ClearGuard) => self.emit(Op::ClearGuard, tile),
+            Some(Instruction::StartCharacterString | Instruction::StartArrayString) => {
+                self.emit(Op::StartString, tile)
+            }
+            Some(Instruction::StartNumber) => self.emit(Op::StartNumber, tile),
+            Some(instruction) => self.emit(Op::Execute(instruction), tile),
+        }
+    }
+}
+
+pub(super) fn compile<T: Tiling>(
+    program: &LanguageState<T>,
+    options: &AnalysisOptions,
+) -> Bytecode<T>
+where
+    T::Edge: FollowableDirection,
+{
+    let graph = ControlFlowGraph::build(program, options);
+    let mut emitter = Emitter {
+        program,
+        ops: vec![],
+        tiles: vec![],
+    };
+
+    // Jumps point at block ids until every block has an address
+    let mut block_addresses = vec![];
+    for (id, block) in graph.blocks.iter().enumerate() {
+        block_addresses.push(emitter.ops.len());
+        for state in &block.states {
+            emitter.state(state);
+        }
+
+        let tile = &block.states.last().unwrap().tile;
+        let next = id + 1;
+        match block.terminator {
+            Terminator::Jump(target) if target != next => emitter.emit(Op::Jump(target), tile),
+            Terminator::Jump(_) | Terminator::Stop | Terminator::RuntimeError => {}
+            Terminator::Branch { straight, turned } => {
+                let instruction = program
+                    .get_char(tile)
+                    .and_then(Instruction::from_char)
+                    .unwrap();
+                emitter.emit(
+                    Op::Branch {
+                        instruction,
+                        turned,
+                    },
+                    tile,
+                );
+                if straight != next {
+                    emitter.emit(Op::Jump(straight), tile);
+                }
+            }
+            Terminator::Guard {
+                next: target,
+                handler,
+            } => {
+                emitter.emit(Op::Guard(handler), tile);
+                if target != next {
+                    emitter.emit(Op::Jump(target), tile);
+                }
+            }
+            Terminator::LeftProgram => emitter.emit(Op::LeftProgram, tile),
+        }
+    }
+
+    for op in &mut emitter.ops {
+        if let Op::Jump(target) | Op::Guard(target) | Op::Branch { turned: target, .. } = op {
+            *target = block_addresses[*target];
+        }
+    }
+
+    Bytecode {
+        ops: emitter.ops,
+        tiles: emitter.tiles,
+        number_format: program.get_number_format().clone(),
+    }
+}
@@ -0,0 +1,169 @@
+//! Runs a program with both the interpreter and the bytecode VM to check they agree
+
+use crate::analysis::cfg::AnalysisOptions;
+use crate::geometry::tiling::Tiling;
+use crate::language::loop_detection::LoopDetection;
+use crate::language::{FollowableDirection, LanguageState, RunOutcome};
+
+use super::vm::Vm;
+use super::Bytecode;
+
+/// A single tile can compile to up to this many operations, like the end of a number followed by
+/// a branch and a jump
+const MAX_OPS_PER_STEP: usize = 3;
+
+#[derive(Clone, Debug)]
+pub struct DifferentialOptions {
+    pub analysis: AnalysisOptions,
+    /// How many steps the interpreter gets before the program is considered to run forever
+    pub max_steps: usize,
+}
+
+impl Default for DifferentialOptions {
+    fn default() -> Self {
+        DifferentialOptions {
+            analysis: AnalysisOptions::default(),
+            max_steps: 100_000,
+        }
+    }
+}
+
+/// The first difference between the interpreter and the VM
+#[derive(Clone, PartialEq, Debug)]
+pub enum Divergence {
+    /// The output differs at byte `position`, `None` if that engine wrote fewer bytes
+    Output {
+        position: usize,
+        interpreter: Option<u8>,
+        vm: Option<u8>,
+    },
+    /// The engines stopped differently, `None` if that engine never stops
+    Outcome {
+        interpreter: Option<RunOutcome>,
+        vm: Option<RunOutcome>,
+    },
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let byte = |byte: &Option<u8>| match byte {
+            Some(byte) => format!("{byte:#04x}"),
+            None => "nothing".to_string(),
+        };
+        let outcome = |outcome: &Option<RunOutcome>| match outcome {
+            Some(outcome) => format!("{outcome:?}"),
+            None => "running forever".to_string(),
+        };
+
+        match self {
+            Divergence::Output {
+                position,
+                interpreter,
+                vm,
+            } => write!(
+                f,
+                "at output byte {position} the interpreter writes {} but the VM writes {}",
+                byte(interpreter),
+                byte(vm)
+            ),
+            Divergence::Outcome { interpreter, vm } => write!(
+                f,
+                "the interpreter ends with {} but the VM ends with {}",
+                outcome(interpreter),
+                outcome(vm)
+            ),
+        }
+    }
+}
+
+/// How an engine ended up after running out of steps
+enum Ending {
+    Stopped(RunOutcome),
+    /// Proven to never stop
+    Forever,
+    /// Out of steps, so it is not known if it would stop
+    Unknown,
+}
+
+impl Ending {
+    fn outcome(&self) -> Option<RunOutcome> {
+        match self {
+            Ending::Stopped(outcome) => Some(*outcome),
+            Ending::Forever | Ending::Unknown => None,
+        }
+    }
+}
+
+/// Runs the program with both engines on the same input and reports the first divergence, if
+/// any. Loop detection stops the interpreter in the same places the VM stops with
+/// [`RunOutcome::LeftProgram`], and programs that don't stop in time are only compared as far as
+/// they got.
+pub fn compare<T: Tiling>(
+    mut program: LanguageState<T>,
+    input: &[u8],
+    options: &DifferentialOptions,
+) -> Option<Divergence>
+where
+    T::Edge: FollowableDirection,
+{
+    let bytecode = Bytecode::compile(&program, &options.analysis);
+
+    let interpreter_output = program.run_detecting_loops(
+        input,
+        LoopDetection {
+            max_empty_steps: options.analysis.max_empty_steps,
+        },
+        options.max_steps,
+    );
+    let interpreter_ending = match program.get_outcome() {
+        Some(RunOutcome::InfiniteLoop { .. }) => Ending::Forever,
+        Some(outcome) => Ending::Stopped(outcome),
+        None => Ending::Unknown,
+    };
+
+    let mut vm = Vm::new(&bytecode);
+    let mut vm_output = vec![];
+    let mut vm_input = input;
+    for _ in 0..options.max_steps * MAX_OPS_PER_STEP {
+        if !vm.is_running() {
+            break;
+        }
+        vm.step(&mut vm_output, &mut vm_input);
+    }
+    let vm_ending = match vm.get_outcome() {
+        Some(outcome) => Ending::Stopped(outcome),
+        None => Ending::Unknown,
+    };
+
+    let shared_length = interpreter_output.len().min(vm_output.len());
+    let position = (0..shared_length)
+        .find(|&position| interpreter_output[position] != vm_output[position])
+        .or_else(|| {
+            // The engine that wrote less is only known to be done if it stopped
+            let shorter = if interpreter_output.len() < vm_output.len() {
+                &interpreter_ending
+            } else {
+                &vm_ending
+            };
+            (interpreter_output.len() != vm_output.len() && !matches!(shorter, Ending::Unknown))
+                .then_some(shared_length)
+        });
+    if let Some(position) = position {
+        return Some(Divergence::Output {
+            position,
+            interpreter: interpreter_output.get(position).copied(),
+            vm: vm_output.get(position).copied(),
+        });
+    }
+
+    let diverged = match (&interpreter_ending, &vm_ending) {
+        (Ending::Stopped(a), Ending::Stopped(b)) => a != b,
+        // The VM gets enough operations to finish whatever the interpreter finished
+        (Ending::Stopped(_), Ending::Unknown) | (Ending::Forever, Ending::Stopped(_)) => true,
+        _ => false,
+    };
+    diverged.then(|| Divergence::Outcome {
+        interpreter: interpreter_ending.outcome(),
+        vm: vm_ending.outcome(),
+    })
+}
@@ -0,0 +1,120 @@
+//! A second backend that runs programs without any geometry. The tiles reachable from the
+//! entry are lowered to a linear list of operations, where the paths between tiles become jumps
+//! and conditional turns become branches.
+mod compiler;
+pub mod differential;
+pub mod vm;
+
+use crate::analysis::cfg::AnalysisOptions;
+use crate::geometry::tile_coordinate::TileCoordinate;
+use crate::geometry::tiling::Tiling;
+use crate::language::error::RuntimeError;
+use crate::language::format::NumberFormat;
+use crate::language::instructions::Instruction;
+use crate::language::{FollowableDirection, LanguageState};
+
+/// The index of an operation in the bytecode
+pub type Address = usize;
+
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) enum Op {
+    /// Evaluates an instruction that doesn't move the instruction pointer
+    Execute(Instruction),
+    /// Evaluates a `^` or `v`, jumping if it would turn
+    Branch {
+        instruction: Instruction,
+        turned: Address,
+    },
+    Jump(Address),
+    /// A `?`, runtime errors jump to the handler from now on
+    Guard(Address),
+    ClearGuard,
+    /// Starts collecting the characters of a string
+    StartString,
+    /// Adds a character to the string being collected
+    Character(f64),
+    /// Pushes the collected characters of a `'` string
+    EndCharacterString,
+    /// Pushes the collected characters of a `"` string as an array
+    EndArrayString,
+    /// Pushes a single character
+    Push(f64),
+    /// Starts collecting the digits of a `$` number
+    StartNumber,
+    Digit(char),
+    EndNumber,
+    /// An error that is always raised, like an invalid escape sequence
+    Error(RuntimeError),
+    /// The instruction pointer crossed too many empty tiles in a row
+    LeftProgram,
+}
+
+impl std::fmt::Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Op::Execute(instruction) => write!(f, "execute {instruction:?}"),
+            Op::Branch {
+                instruction,
+                turned,
+            } => write!(f, "branch {instruction:?} to {turned}"),
+            Op::Jump(address) => write!(f, "jump to {address}"),
+            Op::Guard(handler) => write!(f, "guard to {handler}"),
+            Op::ClearGuard => write!(f, "clear guard"),
+            Op::StartString => write!(f, "start string"),
+            Op::Character(character) => write!(f, "character {character}"),
+            Op::EndCharacterString => write!(f, "end character string"),
+            Op::EndArrayString => write!(f, "end array string"),
+            Op::Push(character) => write!(f, "push {character}"),
+            Op::StartNumber => write!(f, "start number"),
+            Op::Digit(digit) => write!(f, "digit {digit}"),
+            Op::EndNumber => write!(f, "end number"),
+            Op::Error(error) => write!(f, "error {error}"),
+            Op::LeftProgram => write!(f, "left program"),
+        }
+    }
+}
+
+/// A program lowered to operations, see [`vm::Vm`] to run it
+#[derive(Clone, Debug)]
+pub struct Bytecode<T: Tiling> {
+    pub(crate) ops: Vec<Op>,
+    /// The tile each operation was compiled from
+    tiles: Vec<TileCoordinate<T>>,
+    pub(crate) number_format: NumberFormat,
+}
+
+impl<T: Tiling> Bytecode<T>
+where
+    T::Edge: FollowableDirection,
+{
+    /// Compiles every tile reachable from the current instruction pointer of the program. Paths
+    /// that cross more than `options.max_empty_steps` empty tiles in a row end the program with
+    /// [`RunOutcome::LeftProgram`](crate::language::RunOutcome::LeftProgram).
+    pub fn compile(program: &LanguageState<T>, options: &AnalysisOptions) -> Self {
+        compiler::compile(program, options)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// The tile the operation at `address` was compiled from
+    pub fn get_tile(&self, address: Address) -> &TileCoordinate<T> {
+        &self.tiles[address]
+    }
+}
+
+/// Lists the operations, one per line
+impl<T: Tiling> std::fmt::Display for Bytecode<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self.ops.len().to_string().len();
+        self.ops
+            .iter()
+            .enumerate()
+            .try_for_each(|(address, op)| writeln!(f, "{address:>width$} {op}"))
+    }
+}
@@ -0,0 +1,129 @@
+use std::io::{Read, Write};
+
+use crate::geometry::tiling::Tiling;
+use crate::language::error::RuntimeError;
+use crate::language::format::NumberFormat;
+use crate::language::instructions::{self, InstructionPointerBehavior, Mode};
+use crate::language::stack_item::StackItem;
+use crate::language::{literals, RunOutcome};
+
+use super::{Address, Bytecode, Op};
+
+/// Runs [`Bytecode`] with the same input and output as [`LanguageState::step`](crate::language::LanguageState::step)
+pub struct Vm<'a, T: Tiling> {
+    bytecode: &'a Bytecode<T>,
+    address: Address,
+    stacks: Vec<Vec<StackItem>>,
+    /// The characters of the string being collected
+    string: Vec<StackItem>,
+    /// The digits of the `$` number being collected
+    digits: String,
+    guards: Vec<Address>,
+    number_format: NumberFormat,
+    outcome: Option<RunOutcome>,
+}
+
+impl<'a, T: Tiling> Vm<'a, T> {
+    pub fn new(bytecode: &'a Bytecode<T>) -> Self {
+        Vm {
+            bytecode,
+            address: 0,
+            stacks: vec![vec![]],
+            string: vec![],
+            digits: String::new(),
+            guards: vec![],
+            number_format: bytecode.number_format.clone(),
+            outcome: None,
+        }
+    }
+
+    /// Runs a single operation
+    pub fn step<Out: Write, In: Read>(&mut self, out: &mut Out, input: &mut In) {
+        let op = &self.bytecode.ops[self.address];
+        self.address += 1;
+
+        if let Err(error) = self.execute(op, out, input) {
+            match self.guards.pop() {
+                Some(handler) => {
+                    instructions::current_stack(&mut self.stacks).push(error.code().into());
+                    self.address = handler;
+                }
+                None => self.outcome = Some(RunOutcome::RuntimeError(error)),
+            }
+        }
+    }
+
+    fn execute<Out: Write, In: Read>(
+        &mut self,
+        op: &Op,
+        out: &mut Out,
+        input: &mut In,
+    ) -> Result<(), RuntimeError> {
+        match op {
+            Op::Execute(instruction) => {
+                let mut mode = Mode::NormalMode;
+                instruction.evaluate(
+                    &mut mode,
+                    &mut self.stacks,
+                    out,
+                    input,
+                    &mut self.number_format,
+                )?;
+                if let Mode::Stopped(outcome) = mode {
+                    self.outcome = Some(outcome);
+                }
+            }
+            Op::Branch {
+                instruction,
+                turned,
+            } => {
+                let behavior = instruction.evaluate(
+                    &mut Mode::NormalMode,
+                    &mut self.stacks,
+                    out,
+                    input,
+                    &mut self.number_format,
+                )?;
+                if behavior != InstructionPointerBehavior::Straight {
+                    self.address = *turned;
+                }
+            }
+            Op::Jump(target) => self.address = *target,
+            Op::Guard(handler) => self.guards.push(*handler),
+            Op::ClearGuard => {
+                self.guards.pop();
+            }
+            Op::StartString => self.string.clear(),
+            Op::Character(character) => self.string.push(StackItem::Number(*character)),
+            Op::EndCharacterString => {
+                instructions::current_stack(&mut self.stacks).append(&mut self.string)
+            }
+            Op::EndArrayString => instructions::current_stack(&mut self.stacks)
+                .push(StackItem::Array(std::mem::take(&mut self.string))),
+            Op::Push(character) => {
+                instructions::current_stack(&mut self.stacks).push(StackItem::Number(*character))
+            }
+            Op::StartNumber => self.digits.clear(),
+            Op::Digit(digit) => self.digits.push(*digit),
+            Op::EndNumber => instructions::current_stack(&mut self.stacks)
+                .push(literals::parse_number_literal(&self.digits)?.into()),
+            Op::Error(error) => return Err(*error),
+            Op::LeftProgram => self.outcome = Some(RunOutcome::LeftProgram),
+        }
+        Ok(())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.outcome.is_none()
+    }
+
+    /// How the program terminated, `None` while it is still running
+    pub fn get_outcome(&self) -> Option<RunOutcome> {
+        self.outcome
+    }
+
+    /// The address of the next operation
+    pub fn get_address(&self) -> Address {
+        self.address
+    }
+}
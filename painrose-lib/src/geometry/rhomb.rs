@@ -22,6 +22,17 @@ pub enum AbsoluteDirection {
     West,
 }
 
+impl std::fmt::Display for AbsoluteDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AbsoluteDirection::North => "n",
+            AbsoluteDirection::East => "e",
+            AbsoluteDirection::South => "s",
+            AbsoluteDirection::West => "w",
+        })
+    }
+}
+
 impl FollowableDirection for AbsoluteDirection {
     fn turn_left(self) -> Self {
         match self {
@@ -83,6 +94,18 @@ impl TryFrom<char> for Tile {
     }
 }
 
+impl From<Tile> for char {
+    fn from(value: Tile) -> char {
+        match value {
+            Tile::A => 'A',
+            Tile::B => 'B',
+            Tile::C => 'C',
+            Tile::D => 'D',
+            Tile::E => 'E',
+        }
+    }
+}
+
 thread_local! {
     static RHOMB_SHAPE_INFO: AllShapeInfos = AllShapeInfos::default();
 }
@@ -219,6 +242,7 @@ impl draw::DrawableTile for Tile {
         }
     }
 }
+#[derive(Debug)]
 pub struct RhombTiling;
 
 impl Tiling for RhombTiling {
@@ -227,6 +251,7 @@ impl Tiling for RhombTiling {
     type Tile = Tile;
 
     const TILE_PATTERN: &'static [Tile] = &[Tile::C, Tile::E, Tile::D, Tile::B, Tile::A, Tile::A];
+    const NAME: &'static str = "rhomb";
 
     fn get_internal_edge_definition(
         tile: Self::Tile,
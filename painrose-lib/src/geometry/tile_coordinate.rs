@@ -10,16 +10,40 @@ pub struct CoordinateTraversalError<T> {
 
 #[derive(Copy, Clone, PartialEq, Debug, Hash)]
 pub struct CoordinateParsingError<T> {
-    index: usize,
-    kind: CoordinateParsingErrorKind<T>,
+    /// The index of the offending character in the coordinate string
+    pub index: usize,
+    pub kind: CoordinateParsingErrorKind<T>,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug, Hash)]
-enum CoordinateParsingErrorKind<T> {
+pub enum CoordinateParsingErrorKind<T> {
     TraversalError(CoordinateTraversalError<T>),
     ParseError(char),
 }
 
+impl<T: std::fmt::Debug> std::fmt::Display for CoordinateTraversalError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "tile {:?} can not be inside tile {:?}",
+            self.inner_tile, self.outer_tile
+        )
+    }
+}
+
+impl<T: std::fmt::Debug> std::error::Error for CoordinateTraversalError<T> {}
+
+impl<T: std::fmt::Debug> std::fmt::Display for CoordinateParsingError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            CoordinateParsingErrorKind::TraversalError(e) => e.fmt(f),
+            CoordinateParsingErrorKind::ParseError(c) => write!(f, "`{c}` is not a tile"),
+        }
+    }
+}
+
+impl<T: std::fmt::Debug> std::error::Error for CoordinateParsingError<T> {}
+
 pub struct TileCoordinate<T: Tiling>(Vec<T::Tile>);
 
 impl<T: Tiling> std::fmt::Debug for TileCoordinate<T> {
@@ -28,6 +52,18 @@ impl<T: Tiling> std::fmt::Debug for TileCoordinate<T> {
     }
 }
 
+/// Writes the coordinate the way it is parsed, the origin tile is an empty string
+impl<T: Tiling> std::fmt::Display for TileCoordinate<T>
+where
+    T::Tile: Into<char>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0
+            .iter()
+            .try_for_each(|tile| write!(f, "{}", Into::<char>::into(*tile)))
+    }
+}
+
 impl<T: Tiling> Clone for TileCoordinate<T> {
     fn clone(&self) -> Self {
         Self(self.0.clone())
@@ -55,7 +91,7 @@ where
     type Err = CoordinateParsingError<T::Tile>;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        return TileCoordinate::new(
+        let mut item = Self(
             s.chars()
                 .enumerate()
                 .map(|(index, c)| {
@@ -65,18 +101,21 @@ where
                     })
                 })
                 .collect::<Result<Vec<_>, CoordinateParsingError<T::Tile>>>()?,
-        )
-        .map_err(|e| CoordinateParsingError {
-            index: 0,
-            kind: CoordinateParsingErrorKind::TraversalError(e),
-        });
+        );
+        item.normalize()
+            .map_err(|(index, e)| CoordinateParsingError {
+                index,
+                kind: CoordinateParsingErrorKind::TraversalError(e),
+            })?;
+
+        return Ok(item);
     }
 }
 
 impl<T: Tiling> TileCoordinate<T> {
     pub fn new(tiles: Vec<T::Tile>) -> Result<Self, CoordinateTraversalError<T::Tile>> {
         let mut item = Self(tiles);
-        item.normalize()?;
+        item.normalize().map_err(|(_index, e)| e)?;
 
         return Ok(item);
     }
@@ -123,7 +162,8 @@ impl<T: Tiling> TileCoordinate<T> {
         Ok(())
     }
 
-    fn normalize(&mut self) -> Result<(), CoordinateTraversalError<T::Tile>> {
+    /// On failure returns the index of the tile that doesn't fit in its parent as well
+    fn normalize(&mut self) -> Result<(), (usize, CoordinateTraversalError<T::Tile>)> {
         while self.0.len() > 0
             && self.0[self.0.len() - 1]
                 == T::TILE_PATTERN[(self.0.len() - 1) % T::TILE_PATTERN.len()]
@@ -137,12 +177,29 @@ impl<T: Tiling> TileCoordinate<T> {
                 .get(i + 1)
                 .unwrap_or(&T::TILE_PATTERN[(i + 1) % T::TILE_PATTERN.len()]);
 
-            T::can_tile_fit_in_tile(self.0[i], next_tile)?
+            T::can_tile_fit_in_tile(self.0[i], next_tile).map_err(|e| (i, e))?
         }
 
         Ok(())
     }
 
+    /// Moves a coordinate inside the origin supertile at `level` into the supertile made of
+    /// `supertile`, the first of which is at `level`. Tiles of the coordinate from `level` up are
+    /// replaced, so only coordinates no longer than `level` keep their position relative to the
+    /// other tiles of the supertile.
+    pub fn mount(
+        &self,
+        level: usize,
+        supertile: &[T::Tile],
+    ) -> Result<Self, CoordinateTraversalError<T::Tile>> {
+        let tiles = (0..level)
+            .map(|index| self.get_at(index))
+            .chain(supertile.iter().copied())
+            .collect();
+
+        Self::new(tiles)
+    }
+
     pub fn go(&self, edge: T::Edge) -> Result<(Self, T::Edge), CoordinateTraversalError<T::Tile>> {
         let mut copy = self.clone();
 
@@ -37,6 +37,8 @@ pub trait Tiling {
     type Tile: 'static + Copy + Clone + PartialEq + std::fmt::Debug + std::hash::Hash + VariantArray;
 
     const TILE_PATTERN: &'static [Self::Tile];
+    /// The name used to refer to the tiling in the header of a program
+    const NAME: &'static str;
 
     fn get_internal_edge_definition(
         tile: Self::Tile,
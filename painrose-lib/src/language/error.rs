@@ -1,13 +1,378 @@
+use itertools::Itertools;
+
+use crate::geometry::tile_coordinate::{
+    CoordinateParsingError, CoordinateTraversalError, TileCoordinate,
+};
+use crate::geometry::tiling::Tiling;
+
+use super::source_map::SourceLocation;
+
 #[derive(Debug)]
-pub struct ParseError {
+pub struct ParseError<Tile> {
+    /// The line of the error, starting at 0
     pub line: usize,
+    /// The character in the line where the error starts, starting at 0
     pub column: usize,
-    pub kind: ParseErrorKind,
+    /// The number of characters the error spans
+    pub length: usize,
+    pub kind: ParseErrorKind<Tile>,
 }
 
 #[derive(Debug)]
-pub enum ParseErrorKind {
+pub enum ParseErrorKind<Tile> {
     InvalidPrefixError,
-    BadCoordinateError,
-    BadDirectionError,
+    BadCoordinateError(CoordinateParsingError<Tile>),
+    BadDirectionError(String),
+    /// Laying out the code walked into a tile that can't exist
+    TraversalError(CoordinateTraversalError<Tile>),
+    /// The tile is already used by the character at this location
+    CollisionError(Option<SourceLocation>),
+    /// A prefix used an anchor that wasn't defined on an earlier line
+    UndefinedAnchorError(String),
+    /// An anchor with this name was already defined
+    DuplicateAnchorError(String),
+    /// A line number that doesn't refer to an earlier line of code
+    BadLineReferenceError(String),
+    /// A line continues from the previous line of code but there is none
+    NoPreviousLineError,
+    /// An `@` in the code that isn't followed by a layout directive
+    BadDirectiveError(String),
+    /// A `!` header that doesn't exist
+    UnknownHeaderError(String),
+    /// A header that was already given
+    DuplicateHeaderError(String),
+    /// The value of a header can't be understood
+    BadHeaderValueError(String),
+    /// The program was written for another tiling
+    WrongTilingError {
+        found: String,
+        expected: &'static str,
+    },
+    /// The program requires a newer version of the language
+    UnsupportedVersionError(u32),
+    /// A `!version` header after the first line of code
+    LateVersionError,
+    /// An included file could not be read
+    IncludeError(String),
+    /// A file includes itself, directly or through other files
+    IncludeCycleError(String),
+    /// An included file has tiles outside the origin supertile at this level, so it doesn't fit
+    /// in a single supertile
+    IncludeTooLargeError(usize),
+    /// There is an error in an included file
+    IncludedFileError {
+        path: String,
+        error: Box<ParseError<Tile>>,
+    },
+}
+
+impl<Tile: std::fmt::Debug> std::fmt::Display for ParseErrorKind<Tile> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::InvalidPrefixError => {
+                write!(f, "expected a `:` between the coordinate and the code")
+            }
+            ParseErrorKind::BadCoordinateError(e) => write!(f, "invalid coordinate: {e}"),
+            ParseErrorKind::BadDirectionError(direction) => {
+                write!(f, "`{direction}` is not a direction")
+            }
+            ParseErrorKind::TraversalError(e) => write!(f, "can not lay out the code: {e}"),
+            ParseErrorKind::UndefinedAnchorError(name) => {
+                write!(f, "the anchor `@{name}` is not defined")
+            }
+            ParseErrorKind::DuplicateAnchorError(name) => {
+                write!(f, "the anchor `@{name}` is already defined")
+            }
+            ParseErrorKind::BadLineReferenceError(reference) => {
+                write!(f, "`{reference}` is not the number of an earlier line")
+            }
+            ParseErrorKind::NoPreviousLineError => {
+                write!(f, "there is no previous line to continue from")
+            }
+            ParseErrorKind::BadDirectiveError(directive) => {
+                write!(f, "`@{directive}` is not a layout directive")
+            }
+            ParseErrorKind::UnknownHeaderError(header) => {
+                write!(f, "`!{header}` is not a header")
+            }
+            ParseErrorKind::DuplicateHeaderError(header) => {
+                write!(f, "the header `!{header}` is already given")
+            }
+            ParseErrorKind::BadHeaderValueError(value) => {
+                write!(f, "`{value}` is not a valid value for this header")
+            }
+            ParseErrorKind::WrongTilingError { found, expected } => write!(
+                f,
+                "the program is written for the `{found}` tiling but runs on the `{expected}` tiling"
+            ),
+            ParseErrorKind::UnsupportedVersionError(version) => write!(
+                f,
+                "the program requires language version {version} but only version {} is supported",
+                super::LANGUAGE_VERSION
+            ),
+            ParseErrorKind::LateVersionError => {
+                write!(f, "the header `!version` has to come before the code")
+            }
+            ParseErrorKind::IncludeError(e) => write!(f, "can not read the included file: {e}"),
+            ParseErrorKind::IncludeCycleError(path) => {
+                write!(f, "`{path}` is included by itself")
+            }
+            ParseErrorKind::IncludeTooLargeError(level) => write!(
+                f,
+                "the included file does not fit in a supertile at level {level}"
+            ),
+            ParseErrorKind::IncludedFileError { path, error } => write!(
+                f,
+                "{} in the included file `{path}`, line {}, column {}",
+                error.kind,
+                error.line + 1,
+                error.column + 1
+            ),
+            ParseErrorKind::CollisionError(occupied_by) => {
+                write!(f, "the tile is already used")?;
+                write_occupied_by(f, *occupied_by)
+            }
+        }
+    }
+}
+
+fn write_occupied_by(
+    f: &mut std::fmt::Formatter<'_>,
+    occupied_by: Option<SourceLocation>,
+) -> std::fmt::Result {
+    match occupied_by {
+        Some(location) => write!(
+            f,
+            " by the character at line {}, column {}",
+            location.line + 1,
+            location.column + 1
+        ),
+        None => Ok(()),
+    }
+}
+
+impl<Tile: std::fmt::Debug> std::fmt::Display for ParseError<Tile> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.kind,
+            self.line + 1,
+            self.column + 1
+        )
+    }
+}
+
+impl<Tile: std::fmt::Debug + 'static> std::error::Error for ParseError<Tile> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ParseErrorKind::BadCoordinateError(e) => Some(e),
+            ParseErrorKind::TraversalError(e) => Some(e),
+            // The message already has the error of the included file, with its location in that
+            // file, so it isn't returned again as the cause
+            _ => None,
+        }
+    }
+}
+
+impl<Tile: std::fmt::Debug> ParseError<Tile> {
+    /// Renders the error with the offending source line, in the style of rustc:
+    ///
+    /// ```text
+    /// error: invalid coordinate: `x` is not a tile
+    ///  --> 1:3
+    ///   |
+    /// 1 | ABx-e:"Hello"
+    ///   |   ^
+    /// ```
+    pub fn render(&self, source_code: &str) -> String {
+        let line_number = (self.line + 1).to_string();
+        let gutter = " ".repeat(line_number.len());
+        let source_line = source_code.lines().nth(self.line).unwrap_or_default();
+
+        // Tabs are kept so the caret lines up no matter the tab width
+        let padding: String = source_line
+            .chars()
+            .take(self.column)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        [
+            format!("error: {}", self.kind),
+            format!("{gutter}--> {}:{}", line_number, self.column + 1),
+            format!("{gutter} |"),
+            format!("{line_number} | {source_line}"),
+            format!("{gutter} | {padding}{}", "^".repeat(self.length.max(1))),
+        ]
+        .iter()
+        .join("\n")
+            + "\n"
+    }
+}
+
+#[derive(Debug)]
+pub enum JsonError<Tile> {
+    /// The JSON itself is invalid or doesn't have the expected fields
+    SyntaxError(String),
+    /// The program is for another tiling
+    WrongTilingError {
+        found: String,
+        expected: &'static str,
+    },
+    BadCoordinateError {
+        coordinate: String,
+        error: CoordinateParsingError<Tile>,
+    },
+    BadDirectionError(String),
+    /// The same tile is listed more than once
+    DuplicateTileError(String),
+    /// A tile has a line break as its character, which source code can't have
+    LineBreakError(String),
+}
+
+impl<Tile: std::fmt::Debug> std::fmt::Display for JsonError<Tile> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonError::SyntaxError(e) => write!(f, "invalid JSON: {e}"),
+            JsonError::WrongTilingError { found, expected } => write!(
+                f,
+                "the program is written for the `{found}` tiling but runs on the `{expected}` tiling"
+            ),
+            JsonError::BadCoordinateError { coordinate, error } => {
+                write!(f, "invalid coordinate `{coordinate}`: {error}")
+            }
+            JsonError::BadDirectionError(direction) => {
+                write!(f, "`{direction}` is not a direction")
+            }
+            JsonError::DuplicateTileError(coordinate) => {
+                write!(f, "the tile `{coordinate}` is listed more than once")
+            }
+            JsonError::LineBreakError(coordinate) => {
+                write!(f, "the tile `{coordinate}` has a line break as its character")
+            }
+        }
+    }
+}
+
+impl<Tile: std::fmt::Debug + 'static> std::error::Error for JsonError<Tile> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            JsonError::BadCoordinateError { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ParseWarning<T: Tiling> {
+    /// A character was moved further along its line because its tile was already used
+    Collision {
+        /// The character that was moved
+        location: SourceLocation,
+        /// The tile the character would have been placed on
+        tile: TileCoordinate<T>,
+        /// The character that already used the tile
+        occupied_by: Option<SourceLocation>,
+        /// The tile the character was placed on instead
+        placed_at: TileCoordinate<T>,
+    },
+    /// A character of an included file was left out because its tile was already used
+    IncludeCollision {
+        /// The value of the `!include` header
+        location: SourceLocation,
+        tile: TileCoordinate<T>,
+        /// The character that already used the tile
+        occupied_by: Option<SourceLocation>,
+    },
+    /// There is a warning in an included file, its locations and tiles are those of that file
+    IncludedFileWarning {
+        /// The value of the `!include` header
+        location: SourceLocation,
+        path: String,
+        warning: Box<ParseWarning<T>>,
+    },
+}
+
+impl<T: Tiling> std::fmt::Display for ParseWarning<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseWarning::Collision {
+                location,
+                occupied_by,
+                ..
+            } => {
+                write!(
+                    f,
+                    "the character at line {}, column {} was moved because its tile is already used",
+                    location.line + 1,
+                    location.column + 1
+                )?;
+                write_occupied_by(f, *occupied_by)
+            }
+            ParseWarning::IncludeCollision {
+                location,
+                occupied_by,
+                ..
+            } => {
+                write!(
+                    f,
+                    "the file included at line {}, column {} has a character that was left out because its tile is already used",
+                    location.line + 1,
+                    location.column + 1
+                )?;
+                write_occupied_by(f, *occupied_by)
+            }
+            ParseWarning::IncludedFileWarning {
+                location,
+                path,
+                warning,
+            } => write!(
+                f,
+                "in the file `{path}` included at line {}, column {}: {warning}",
+                location.line + 1,
+                location.column + 1
+            ),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RuntimeError {
+    DivisionByZero,
+    IndexOutOfBounds,
+    InvalidNumber,
+    EndOfInput,
+    InvalidEscape,
+    InvalidExitCode,
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeError::DivisionByZero => write!(f, "division by zero"),
+            RuntimeError::IndexOutOfBounds => write!(f, "index out of bounds"),
+            RuntimeError::InvalidNumber => write!(f, "not a valid number"),
+            RuntimeError::EndOfInput => write!(f, "unexpected end of input"),
+            RuntimeError::InvalidEscape => write!(f, "invalid escape sequence"),
+            RuntimeError::InvalidExitCode => {
+                write!(f, "the exit code has to be a number from 0 to 255")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl RuntimeError {
+    /// The error code pushed on the stack when a guard catches this error
+    pub fn code(self) -> u8 {
+        match self {
+            RuntimeError::DivisionByZero => 1,
+            RuntimeError::IndexOutOfBounds => 2,
+            RuntimeError::InvalidNumber => 3,
+            RuntimeError::EndOfInput => 4,
+            RuntimeError::InvalidEscape => 5,
+            RuntimeError::InvalidExitCode => 6,
+        }
+    }
 }
@@ -0,0 +1,92 @@
+use std::io::Write;
+
+use itertools::Itertools;
+
+use super::stack_item::StackItem;
+
+const MAX_PRECISION: usize = 64;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Notation {
+    /// The shortest representation that round trips, like `1.5` or `100`
+    Default,
+    /// Rounded towards zero, like `1`
+    Integer,
+    /// A fixed number of decimals, like `1.500`
+    Fixed(usize),
+    /// Scientific notation with a fixed number of decimals, like `1.50e0`
+    Scientific(usize),
+}
+
+/// Controls how `N` writes numbers
+#[derive(Clone, PartialEq, Debug)]
+pub struct NumberFormat {
+    pub notation: Notation,
+    /// Written after every number, or after every array when using structured arrays
+    pub separator: String,
+    /// Write arrays like `[1, 2, [3]]` instead of flattening them
+    pub structured_arrays: bool,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat {
+            notation: Notation::Default,
+            separator: " ".to_string(),
+            structured_arrays: false,
+        }
+    }
+}
+
+impl Notation {
+    /// The notation selected by the `F` instruction, some notations need a precision as well. The
+    /// codes 4 and 5 switch structured arrays on and off instead.
+    pub(super) fn from_code(code: f64, precision: impl FnOnce() -> f64) -> Self {
+        match code as i32 {
+            1 => Notation::Integer,
+            2 => Notation::Fixed((precision() as usize).min(MAX_PRECISION)),
+            3 => Notation::Scientific((precision() as usize).min(MAX_PRECISION)),
+            _ => Notation::Default,
+        }
+    }
+}
+
+impl NumberFormat {
+    pub fn format_number(&self, number: f64) -> String {
+        match self.notation {
+            Notation::Default => format!("{number}"),
+            Notation::Integer if number.is_finite() => format!("{}", number.trunc() as i64),
+            Notation::Integer => format!("{number}"),
+            Notation::Fixed(precision) => format!("{number:.precision$}"),
+            Notation::Scientific(precision) => format!("{number:.precision$e}"),
+        }
+    }
+
+    fn format_structured(&self, item: &StackItem) -> String {
+        match item {
+            StackItem::Number(number) => self.format_number(*number),
+            StackItem::Array(arr) => format!(
+                "[{}]",
+                arr.iter().map(|k| self.format_structured(k)).join(", ")
+            ),
+        }
+    }
+
+    pub(super) fn write_stack_item<Out: Write>(
+        &self,
+        out: &mut Out,
+        item: &StackItem,
+    ) -> std::io::Result<()> {
+        if self.structured_arrays {
+            return write!(out, "{}{}", self.format_structured(item), self.separator);
+        }
+
+        let mut result = Ok(());
+        item.for_each_recusrive(&mut |k| {
+            if result.is_ok() {
+                result = write!(out, "{}{}", self.format_number(k), self.separator);
+            }
+        });
+        result
+    }
+}
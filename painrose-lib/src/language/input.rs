@@ -0,0 +1,71 @@
+//! Reading the input of the input instructions
+//!
+//! `i` reads a character, `w` a line and `l` a word, all as UTF-8. `n` reads a word and parses it.
+//! At the end of the input `i`, `w` and `l` push -1, `n` raises
+//! [`RuntimeError::EndOfInput`](super::error::RuntimeError::EndOfInput).
+//!
+//! The difference is on purpose: -1 is never a character code or an array, so a program can tell it
+//! apart from anything `i`, `w` and `l` read. `n` could have read -1 from the input, so the end of
+//! the input has to be an error that a guard can catch.
+
+use std::io::Read;
+
+use super::stack_item::StackItem;
+
+/// Reads one UTF-8 character, invalid sequences become the replacement character
+pub(super) fn read_char<In: Read>(input: &mut In) -> Option<char> {
+    let mut buffer = [0u8; 4];
+    input.read_exact(&mut buffer[..1]).ok()?;
+
+    let length = match buffer[0].leading_ones() {
+        2..=4 => buffer[0].leading_ones() as usize,
+        _ => 1,
+    };
+    input.read_exact(&mut buffer[1..length]).ok()?;
+
+    Some(
+        std::str::from_utf8(&buffer[..length])
+            .map_or(char::REPLACEMENT_CHARACTER, |k| k.chars().next().unwrap()),
+    )
+}
+
+/// Reads up to the next line break, which is dropped along with a `\r` before it
+pub(super) fn read_line<In: Read>(input: &mut In) -> Option<String> {
+    let mut line = String::new();
+    loop {
+        match read_char(input) {
+            None if line.is_empty() => return None,
+            None | Some('\n') => break,
+            Some(char) => line.push(char),
+        }
+    }
+    if line.ends_with('\r') {
+        line.pop();
+    }
+    Some(line)
+}
+
+/// Skips whitespace, then reads up to the next whitespace
+pub(super) fn read_word<In: Read>(input: &mut In) -> Option<String> {
+    let mut word = String::new();
+    loop {
+        match read_char(input) {
+            None if word.is_empty() => return None,
+            Some(char) if char.is_whitespace() && word.is_empty() => (),
+            None => break,
+            Some(char) if char.is_whitespace() => break,
+            Some(char) => word.push(char),
+        }
+    }
+    Some(word)
+}
+
+/// An array with the code points of a string
+pub(super) fn string_to_stack_item(string: String) -> StackItem {
+    StackItem::Array(
+        string
+            .chars()
+            .map(|k| StackItem::Number(k as u32 as f64))
+            .collect(),
+    )
+}
@@ -2,7 +2,11 @@ use std::io::{Read, Write};
 
 use strum::EnumString;
 
+use super::error::RuntimeError;
+use super::format::{Notation, NumberFormat};
+use super::input::{read_char, read_line, read_word, string_to_stack_item};
 use super::stack_item::StackItem;
+use super::RunOutcome;
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug, EnumString)]
 pub enum Instruction {
@@ -45,6 +49,13 @@ pub enum Instruction {
     UnwrapArray,
     #[strum(serialize = "a")] // ☒
     WrapArray,
+    // Stack of stacks
+    #[strum(serialize = "B")]
+    PushStack,
+    #[strum(serialize = "E")]
+    PopStack,
+    #[strum(serialize = "X")]
+    SwapStacks,
     // String
     #[strum(serialize = "'")] // '
     StartCharacterString,
@@ -54,6 +65,8 @@ pub enum Instruction {
     StartCharacter,
     // Constants
     Const(u8),
+    #[strum(serialize = "$")]
+    StartNumber,
     // Math
     #[strum(serialize = "+")]
     Add,
@@ -81,14 +94,30 @@ pub enum Instruction {
     OutputN,
     #[strum(serialize = "N")]
     OutputNumber,
+    #[strum(serialize = "F")]
+    SetNumberFormat,
     // Array
     #[strum(serialize = "[")]
     GetArrayN,
     #[strum(serialize = "]")]
     PutArrayN,
+    // Error handling
+    #[strum(serialize = "?")]
+    Guard,
+    #[strum(serialize = "&")]
+    ClearGuard,
     // Exit
     #[strum(serialize = ";")]
     Quit,
+    #[strum(serialize = "Q")]
+    Exit,
+}
+
+/// How many items an instruction takes from the current stack and how many it puts back
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct StackEffect {
+    pub pops: usize,
+    pub pushes: usize,
 }
 
 #[derive(PartialEq, Debug, Copy, Clone)]
@@ -105,7 +134,16 @@ pub enum Mode {
     CharStringMode(Vec<StackItem>),
     ArrayStringMode(Vec<StackItem>),
     CharMode,
-    Stopped,
+    Number(String),
+    Escape(Box<Mode>, String),
+    Stopped(RunOutcome),
+}
+
+pub(crate) fn current_stack(stacks: &mut Vec<Vec<StackItem>>) -> &mut Vec<StackItem> {
+    if stacks.is_empty() {
+        stacks.push(vec![]);
+    }
+    stacks.last_mut().unwrap()
 }
 
 fn top_of_stack_or_default(stack: &mut Vec<StackItem>) -> StackItem {
@@ -154,6 +192,14 @@ fn array_wrap(stack: &mut Vec<StackItem>, n: StackItem) -> StackItem {
     }
 }
 
+fn array_index(array: &[StackItem], index: f64) -> Result<usize, RuntimeError> {
+    if index >= 0.0 && (index as usize) < array.len() {
+        Ok(index as usize)
+    } else {
+        Err(RuntimeError::IndexOutOfBounds)
+    }
+}
+
 impl Instruction {
     pub fn is_nonconditional_movement_instruction(self) -> bool {
         match self {
@@ -162,6 +208,42 @@ impl Instruction {
         }
     }
 
+    /// The effect on the current stack, `None` when it depends on the values on the stack or the
+    /// instruction changes which stack is current. Strings and numbers started by an instruction
+    /// are pushed later.
+    pub fn stack_effect(self) -> Option<StackEffect> {
+        let (pops, pushes) = match self {
+            Self::TurnLeft | Self::TurnRight | Self::TurnAround => (0, 0),
+            Self::TurnLeftIf | Self::TurnRightIf => (1, 0),
+            Self::Less | Self::Greater | Self::Equal => (2, 1),
+            Self::Duplicate => (1, 2),
+            Self::DuplicateTwo => (2, 4),
+            Self::PopTop => (1, 0),
+            Self::Swap => (2, 2),
+            Self::RotateLeft | Self::RotateRight => (0, 0),
+            // Replaces the index with the copy, items it can't reach read as 0
+            Self::CopyNth => (1, 1),
+            Self::DuplicateN | Self::UnwrapArray | Self::WrapArray => return None,
+            Self::PushStack | Self::PopStack | Self::SwapStacks => return None,
+            Self::StartCharacterString
+            | Self::StartArrayString
+            | Self::StartCharacter
+            | Self::StartNumber => (0, 0),
+            Self::Const(_) => (0, 1),
+            Self::Add | Self::Subtract | Self::Multiply | Self::Divide => (2, 1),
+            Self::Negate => (1, 1),
+            Self::InputCharacter | Self::InputLine | Self::InputWord | Self::InputNumber => (0, 1),
+            Self::OutputCharacter | Self::OutputNumber => (1, 0),
+            Self::OutputN | Self::SetNumberFormat => return None,
+            // The result is only pushed when the operand is an array, otherwise nothing is
+            Self::GetArrayN => (2, 1),
+            Self::PutArrayN => (3, 1),
+            Self::Guard | Self::ClearGuard | Self::Quit => (0, 0),
+            Self::Exit => (1, 0),
+        };
+        Some(StackEffect { pops, pushes })
+    }
+
     pub fn from_char(item: char) -> Option<Self> {
         let mut tmp = [0u8; 4];
         let string = item.encode_utf8(&mut tmp);
@@ -187,11 +269,13 @@ impl Instruction {
     pub fn evaluate<Out: Write, In: Read>(
         self,
         mode: &mut Mode,
-        stack: &mut Vec<StackItem>,
+        stacks: &mut Vec<Vec<StackItem>>,
         out: &mut Out,
         input: &mut In,
-    ) -> InstructionPointerBehavior {
+        number_format: &mut NumberFormat,
+    ) -> Result<InstructionPointerBehavior, RuntimeError> {
         let mut behavior = InstructionPointerBehavior::Straight;
+        let stack = current_stack(stacks);
         match self {
             Instruction::TurnLeft => behavior = InstructionPointerBehavior::Left,
             Instruction::TurnRight => behavior = InstructionPointerBehavior::Right,
@@ -235,11 +319,19 @@ impl Instruction {
             }
             Instruction::Swap => {
                 let (a, b) = top_two_of_stack_or_default(stack);
-                stack.push(b);
                 stack.push(a);
+                stack.push(b);
+            }
+            Instruction::RotateLeft => {
+                if !stack.is_empty() {
+                    stack.rotate_left(1)
+                }
+            }
+            Instruction::RotateRight => {
+                if !stack.is_empty() {
+                    stack.rotate_right(1)
+                }
             }
-            Instruction::RotateLeft => stack.rotate_left(1),
-            Instruction::RotateRight => stack.rotate_right(1),
             Instruction::DuplicateN => {
                 let number_of_stack_items_to_copy = top_of_stack_or_default(stack);
                 match number_of_stack_items_to_copy {
@@ -271,10 +363,30 @@ impl Instruction {
                 let wrapped_stack_item = array_wrap(stack, n);
                 stack.push(wrapped_stack_item);
             }
+            Instruction::PushStack => stacks.push(vec![]),
+            Instruction::PopStack => {
+                // Only the items that are actually on the stack get moved
+                let count = match top_of_stack_or_default(stack) {
+                    StackItem::Number(num) => (num as usize).min(stack.len()),
+                    StackItem::Array(_) => return Err(RuntimeError::InvalidNumber),
+                };
+                let moved_items = stack.split_off(stack.len() - count);
+
+                stacks.pop();
+                current_stack(stacks).extend(moved_items);
+            }
+            Instruction::SwapStacks => {
+                if stacks.len() < 2 {
+                    stacks.insert(0, vec![]);
+                }
+                let length = stacks.len();
+                stacks.swap(length - 1, length - 2);
+            }
             Instruction::StartCharacterString => *mode = Mode::CharStringMode(vec![]),
             Instruction::StartArrayString => *mode = Mode::ArrayStringMode(vec![]),
             Instruction::StartCharacter => *mode = Mode::CharMode,
             Instruction::Const(i) => stack.push(i.into()),
+            Instruction::StartNumber => *mode = Mode::Number(String::new()),
             Instruction::Add => {
                 let (a, b) = top_two_of_stack_or_default(stack);
                 stack.push(a + b);
@@ -289,16 +401,33 @@ impl Instruction {
             }
             Instruction::Divide => {
                 let (a, b) = top_two_of_stack_or_default(stack);
-                stack.push(a / b);
+                stack.push(a.checked_div(b)?);
             }
             Instruction::Negate => {
                 let m = top_of_stack_or_default(stack);
                 stack.push(-m);
             }
-            Instruction::InputCharacter => todo!(),
-            Instruction::InputLine => todo!(),
-            Instruction::InputWord => todo!(),
-            Instruction::InputNumber => todo!(),
+            Instruction::InputCharacter => stack.push(
+                read_char(input)
+                    .map(|k| k as u32 as f64)
+                    .unwrap_or(-1.0)
+                    .into(),
+            ),
+            Instruction::InputLine => stack.push(
+                read_line(input)
+                    .map(string_to_stack_item)
+                    .unwrap_or(StackItem::Number(-1.0)),
+            ),
+            Instruction::InputWord => stack.push(
+                read_word(input)
+                    .map(string_to_stack_item)
+                    .unwrap_or(StackItem::Number(-1.0)),
+            ),
+            Instruction::InputNumber => {
+                let word = read_word(input).ok_or(RuntimeError::EndOfInput)?;
+                let number: f64 = word.parse().map_err(|_| RuntimeError::InvalidNumber)?;
+                stack.push(number.into());
+            }
             Instruction::OutputCharacter => {
                 let top = top_of_stack_or_default(stack);
                 top.for_each_recusrive(&mut |k| {
@@ -320,8 +449,32 @@ impl Instruction {
             }
             Instruction::OutputNumber => {
                 let top = top_of_stack_or_default(stack);
-                top.for_each_recusrive(&mut |k| write!(out, "{} ", k).unwrap())
+                number_format.write_stack_item(out, &top).unwrap()
             }
+            Instruction::SetNumberFormat => match top_of_stack_or_default(stack) {
+                StackItem::Number(code) if code as i32 == 4 => {
+                    number_format.structured_arrays = true
+                }
+                StackItem::Number(code) if code as i32 == 5 => {
+                    number_format.structured_arrays = false
+                }
+                StackItem::Number(code) => {
+                    number_format.notation =
+                        Notation::from_code(code, || match top_of_stack_or_default(stack) {
+                            StackItem::Number(precision) => precision,
+                            StackItem::Array(_) => 0.0,
+                        })
+                }
+                // An array is the text of the new separator
+                separator @ StackItem::Array(_) => {
+                    number_format.separator.clear();
+                    separator.for_each_recusrive(&mut |k| {
+                        number_format
+                            .separator
+                            .push(char::from_u32(k as u32).unwrap_or('?'))
+                    });
+                }
+            },
             Instruction::GetArrayN => {
                 let n = top_of_stack_or_default(stack);
                 let array = top_of_stack_or_default(stack);
@@ -329,7 +482,9 @@ impl Instruction {
                 match array {
                     StackItem::Number(n) => (),
                     StackItem::Array(arr) => {
-                        stack.push(n.apply_unary_operator(&|k| arr[k as usize].clone()));
+                        stack.push(n.try_apply_unary_operator(&|k| {
+                            array_index(&arr, k).map(|index| arr[index].clone())
+                        })?);
                     }
                 }
             }
@@ -341,14 +496,30 @@ impl Instruction {
                 match array {
                     StackItem::Number(n) => (),
                     StackItem::Array(mut arr) => {
-                        n.for_each_recusrive(&mut |k| arr[k as usize] = value.clone());
+                        let mut result = Ok(());
+                        n.for_each_recusrive(&mut |k| match array_index(&arr, k) {
+                            Ok(index) => arr[index] = value.clone(),
+                            Err(e) => result = Err(e),
+                        });
+                        result?;
                         stack.push(StackItem::Array(arr));
                     }
                 }
             }
+            // Guards need to know the position of the instruction pointer, so they are handled by
+            // `LanguageState::step` instead
+            Instruction::Guard | Instruction::ClearGuard => (),
 
-            Instruction::Quit => *mode = Mode::Stopped,
+            Instruction::Quit => *mode = Mode::Stopped(RunOutcome::Quit),
+            Instruction::Exit => {
+                // Process statuses only go up to 255, larger codes would wrap around
+                let exit_code = match top_of_stack_or_default(stack) {
+                    StackItem::Number(n) if n > -1.0 && n < 256.0 => n as i32,
+                    _ => return Err(RuntimeError::InvalidExitCode),
+                };
+                *mode = Mode::Stopped(RunOutcome::Exit(exit_code));
+            }
         }
-        return behavior;
+        return Ok(behavior);
     }
 }
@@ -0,0 +1,146 @@
+//! A format that lists every tile of a program with its character, so the placement of the code
+//! doesn't depend on the rules of the parser
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use strum::VariantArray;
+
+use crate::geometry::tile_coordinate::TileCoordinate;
+use crate::geometry::tiling::Tiling;
+
+use super::error::{JsonError, ParseError};
+use super::instructions::Instruction;
+use super::source_map::SourceMap;
+use super::{FollowableDirection, LanguageState};
+
+#[derive(Serialize, Deserialize)]
+struct JsonEntry {
+    coordinate: String,
+    direction: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonTile {
+    coordinate: String,
+    character: char,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonProgram {
+    tiling: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entry: Option<JsonEntry>,
+    tiles: Vec<JsonTile>,
+}
+
+pub(super) fn read<T: Tiling>(json: &str) -> Result<LanguageState<T>, JsonError<T::Tile>>
+where
+    T::Tile: TryFrom<char, Error = char>,
+    T::Edge: FollowableDirection + FromStr,
+{
+    let program: JsonProgram =
+        serde_json::from_str(json).map_err(|e| JsonError::SyntaxError(e.to_string()))?;
+
+    if program.tiling != T::NAME {
+        return Err(JsonError::WrongTilingError {
+            found: program.tiling,
+            expected: T::NAME,
+        });
+    }
+
+    let parse_coordinate = |coordinate: &str| {
+        coordinate
+            .parse::<TileCoordinate<T>>()
+            .map_err(|error| JsonError::BadCoordinateError {
+                coordinate: coordinate.to_string(),
+                error,
+            })
+    };
+
+    let mut code = HashMap::new();
+    for tile in program.tiles {
+        let coordinate = parse_coordinate(&tile.coordinate)?;
+        // It could never be written back as source code
+        if matches!(tile.character, '\n' | '\r') {
+            return Err(JsonError::LineBreakError(tile.coordinate));
+        }
+        if code
+            .insert(
+                coordinate,
+                (tile.character, Instruction::from_char(tile.character)),
+            )
+            .is_some()
+        {
+            return Err(JsonError::DuplicateTileError(tile.coordinate));
+        }
+    }
+
+    let entry = match program.entry {
+        Some(entry) => (
+            parse_coordinate(&entry.coordinate)?,
+            entry
+                .direction
+                .parse()
+                .map_err(|_| JsonError::BadDirectionError(entry.direction))?,
+        ),
+        None => (TileCoordinate::new(vec![]).unwrap(), T::Edge::VARIANTS[0]),
+    };
+
+    Ok(LanguageState::new(code, SourceMap::default(), entry))
+}
+
+/// Lists the tiles sorted by their coordinate, along with the entry point the program was loaded
+/// with
+pub(super) fn write<T: Tiling>(state: &LanguageState<T>) -> String
+where
+    T::Tile: Into<char>,
+    T::Edge: FollowableDirection + Display,
+{
+    let mut tiles: Vec<_> = state
+        .code
+        .iter()
+        .map(|(coordinate, (character, _))| JsonTile {
+            coordinate: coordinate.to_string(),
+            character: *character,
+        })
+        .collect();
+    tiles.sort_by(|a, b| {
+        (a.coordinate.len(), &a.coordinate).cmp(&(b.coordinate.len(), &b.coordinate))
+    });
+
+    let (entry_tile, entry_direction) = &state.entry;
+    let entry = (*entry_tile != TileCoordinate::new(vec![]).unwrap()
+        || *entry_direction != T::Edge::VARIANTS[0])
+        .then(|| JsonEntry {
+            coordinate: entry_tile.to_string(),
+            direction: entry_direction.to_string(),
+        });
+
+    serde_json::to_string_pretty(&JsonProgram {
+        tiling: T::NAME.to_string(),
+        entry,
+        tiles,
+    })
+    .unwrap()
+}
+
+/// Converts a program in the text format to the JSON format
+pub fn source_to_json<T: Tiling>(source_code: &str) -> Result<String, ParseError<T::Tile>>
+where
+    T::Tile: TryFrom<char, Error = char> + Into<char>,
+    T::Edge: FollowableDirection + FromStr + Display,
+{
+    Ok(LanguageState::<T>::new_from_string(source_code.to_string())?.to_json())
+}
+
+/// Converts a program in the JSON format to the text format
+pub fn json_to_source<T: Tiling>(json: &str) -> Result<String, JsonError<T::Tile>>
+where
+    T::Tile: TryFrom<char, Error = char> + Into<char>,
+    T::Edge: FollowableDirection + FromStr + Display,
+{
+    Ok(LanguageState::<T>::new_from_json(json)?.serialize())
+}
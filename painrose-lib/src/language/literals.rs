@@ -0,0 +1,46 @@
+use super::error::RuntimeError;
+
+const MAX_UNICODE_ESCAPE_LENGTH: usize = "u{10FFFF}".len();
+
+/// Resolves the characters following a `\` in a string
+///
+/// Returns `None` while the escape sequence is still incomplete.
+pub(crate) fn resolve_escape(sequence: &str) -> Result<Option<char>, RuntimeError> {
+    match sequence {
+        "n" => Ok(Some('\n')),
+        "t" => Ok(Some('\t')),
+        "r" => Ok(Some('\r')),
+        "0" => Ok(Some('\0')),
+        "\\" | "\"" | "'" | "`" => Ok(sequence.chars().next()),
+        "u" | "u{" => Ok(None),
+        _ => {
+            let Some(digits) = sequence.strip_prefix("u{") else {
+                return Err(RuntimeError::InvalidEscape);
+            };
+
+            match digits.strip_suffix('}') {
+                Some(digits) => u32::from_str_radix(digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .map(Some)
+                    .ok_or(RuntimeError::InvalidEscape),
+                None if sequence.len() < MAX_UNICODE_ESCAPE_LENGTH
+                    && digits.chars().all(|k| k.is_ascii_hexdigit()) =>
+                {
+                    Ok(None)
+                }
+                None => Err(RuntimeError::InvalidEscape),
+            }
+        }
+    }
+}
+
+pub(crate) fn is_number_literal_character(digits: &str, character: char) -> bool {
+    character.is_ascii_digit() || (character == '.' && !digits.contains('.'))
+}
+
+/// Parses the digits of a number literal, a literal without any digits like `$` or `$.` is an
+/// error
+pub(crate) fn parse_number_literal(digits: &str) -> Result<f64, RuntimeError> {
+    digits.parse().map_err(|_| RuntimeError::InvalidNumber)
+}
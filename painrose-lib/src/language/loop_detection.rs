@@ -0,0 +1,124 @@
+use std::io::{Read, Write};
+
+/// Settings for stopping programs that can never finish, enabled with
+/// [`LanguageState::set_loop_detection`](super::LanguageState::set_loop_detection)
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct LoopDetection {
+    /// How many empty tiles in a row the instruction pointer can cross before the program is
+    /// stopped for leaving the code
+    pub max_empty_steps: usize,
+}
+
+impl Default for LoopDetection {
+    fn default() -> Self {
+        LoopDetection {
+            max_empty_steps: 1000,
+        }
+    }
+}
+
+/// Finds loops with Brent's algorithm: a running program is compared to a single saved state,
+/// which is replaced after 1, 2, 4, ... steps. Once the program is in a loop the saved state is
+/// part of it after a while, and the program comes back to it within the length of the loop.
+#[derive(Clone, Debug)]
+pub(super) struct LoopDetector<S> {
+    options: LoopDetection,
+    /// A state since the last input or output
+    saved: Option<S>,
+    /// How many steps ago the state was saved
+    since_saved: usize,
+    /// How many steps the saved state is kept before saving a new one
+    power: usize,
+    empty_steps: usize,
+    /// The length of the loop once one is found
+    loop_length: Option<usize>,
+}
+
+impl<S> LoopDetector<S> {
+    pub(super) fn new(options: LoopDetection) -> Self {
+        LoopDetector {
+            options,
+            saved: None,
+            since_saved: 0,
+            power: 1,
+            empty_steps: 0,
+            loop_length: None,
+        }
+    }
+
+    /// Records the state after a step, finding a loop if it is the saved state and there was no
+    /// I/O in between. `is_saved` compares the current state to the saved one, and `save` copies
+    /// it when it has to be saved.
+    pub(super) fn visit(
+        &mut self,
+        did_io: bool,
+        is_saved: impl FnOnce(&S) -> bool,
+        save: impl FnOnce() -> S,
+    ) {
+        if did_io {
+            self.saved = None;
+        }
+
+        if let Some(saved) = &self.saved {
+            self.since_saved += 1;
+            if is_saved(saved) {
+                self.loop_length = Some(self.since_saved);
+                return;
+            }
+            if self.since_saved < self.power {
+                return;
+            }
+            self.power *= 2;
+        } else {
+            self.power = 1;
+        }
+        self.saved = Some(save());
+        self.since_saved = 0;
+    }
+
+    /// The length of the loop the program is in, if one was found
+    pub(super) fn loop_length(&self) -> Option<usize> {
+        self.loop_length
+    }
+
+    /// Records if the instruction pointer is on an empty tile after moving `distance` tiles,
+    /// returns `true` once it crossed too many of them in a row
+    pub(super) fn wander(&mut self, on_empty_tile: bool, distance: usize) -> bool {
+        if on_empty_tile {
+            self.empty_steps += distance;
+        } else {
+            self.empty_steps = 0;
+        }
+        self.empty_steps > self.options.max_empty_steps
+    }
+}
+
+/// Wraps the input or output of a step to tell if it was used
+pub(super) struct Watched<'a, S> {
+    inner: &'a mut S,
+    pub(super) used: bool,
+}
+
+impl<'a, S> Watched<'a, S> {
+    pub(super) fn new(inner: &'a mut S) -> Self {
+        Watched { inner, used: false }
+    }
+}
+
+impl<S: Write> Write for Watched<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.used = true;
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: Read> Read for Watched<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.used = true;
+        self.inner.read(buf)
+    }
+}
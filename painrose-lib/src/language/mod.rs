@@ -1,80 +1,253 @@
 mod draw;
-mod error;
-mod instructions;
-mod stack_item;
+pub mod error;
+pub mod format;
+mod input;
+pub(crate) mod instructions;
+pub mod json;
+pub(crate) mod literals;
+pub mod loop_detection;
+pub mod parser;
+mod path_cache;
+mod serialize;
+pub mod source_map;
+pub(crate) mod stack_item;
 
 use std::io::Read;
 use std::str::FromStr;
 use std::{collections::HashMap, io::Write};
-use strum::VariantArray;
 
 use crate::geometry::draw::{DrawableTile, ShapeInfo};
 use crate::geometry::tile_coordinate::TileCoordinate;
 use crate::geometry::tiling::Tiling;
 
+use itertools::Itertools;
+
 use self::instructions::{Instruction, InstructionPointerBehavior, Mode};
 
+/// The newest language version this implementation can run, programs can require a version with
+/// a `!version` header
+pub const LANGUAGE_VERSION: u32 = 2;
+
+fn push_string_character(
+    mode: Mode,
+    stacks: &mut Vec<Vec<stack_item::StackItem>>,
+    character: stack_item::StackItem,
+) -> Mode {
+    match mode {
+        Mode::CharMode => {
+            instructions::current_stack(stacks).push(character);
+            Mode::NormalMode
+        }
+        Mode::ArrayStringMode(mut e) => {
+            e.push(character);
+            Mode::ArrayStringMode(e)
+        }
+        Mode::CharStringMode(mut e) => {
+            e.push(character);
+            Mode::CharStringMode(e)
+        }
+        mode => mode,
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RunOutcome {
+    /// The program reached a `;`
+    Quit,
+    /// The program reached a `Q` with the given exit code, from 0 to 255
+    Exit(i32),
+    /// A runtime error happened that wasn't caught by a guard
+    RuntimeError(error::RuntimeError),
+    /// Loop detection found the program back in an earlier state without any I/O since, so it
+    /// would repeat the same `length` steps forever. The tiles of the loop are available from
+    /// [`LanguageState::get_loop_tiles`].
+    InfiniteLoop { length: usize },
+    /// Loop detection found the instruction pointer crossing too many empty tiles in a row
+    LeftProgram,
+}
+
+impl RunOutcome {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            RunOutcome::Quit => 0,
+            RunOutcome::Exit(code) => code,
+            RunOutcome::RuntimeError(_) => 1,
+            RunOutcome::InfiniteLoop { .. } | RunOutcome::LeftProgram => 1,
+        }
+    }
+}
+
 pub trait FollowableDirection: Copy {
     fn turn_left(self) -> Self;
     fn turn_right(self) -> Self;
     fn opposite(self) -> Self;
 }
 
-#[derive(Clone, Debug)]
+/// Everything that decides what the program does next: the instruction pointer and direction,
+/// the mode, the stacks, the guards and the number format
+type Snapshot<T> = (
+    TileCoordinate<T>,
+    <T as Tiling>::Edge,
+    Mode,
+    Vec<Vec<stack_item::StackItem>>,
+    Vec<(TileCoordinate<T>, <T as Tiling>::Edge)>,
+    format::NumberFormat,
+);
+
+#[derive(Debug)]
 pub struct LanguageState<T: Tiling>
 where
     T::Edge: FollowableDirection,
 {
     code: HashMap<TileCoordinate<T>, (char, Option<Instruction>)>,
+    /// The tile and direction the program started at
+    entry: (TileCoordinate<T>, T::Edge),
     instruction_pointer: TileCoordinate<T>,
     direction: T::Edge,
-    stack: Vec<stack_item::StackItem>,
+    stacks: Vec<Vec<stack_item::StackItem>>,
     mode: Mode,
+    guards: Vec<(TileCoordinate<T>, T::Edge)>,
+    number_format: format::NumberFormat,
+    source_map: source_map::SourceMap<T>,
+    loop_detector: Option<loop_detection::LoopDetector<Snapshot<T>>>,
+    loop_tiles: Vec<TileCoordinate<T>>,
+    path_cache: Option<path_cache::PathCache<T>>,
+}
+
+impl<T: Tiling> Clone for LanguageState<T>
+where
+    T::Edge: FollowableDirection,
+{
+    fn clone(&self) -> Self {
+        LanguageState {
+            code: self.code.clone(),
+            entry: self.entry.clone(),
+            instruction_pointer: self.instruction_pointer.clone(),
+            direction: self.direction,
+            stacks: self.stacks.clone(),
+            mode: self.mode.clone(),
+            guards: self.guards.clone(),
+            number_format: self.number_format.clone(),
+            source_map: self.source_map.clone(),
+            loop_detector: self.loop_detector.clone(),
+            loop_tiles: self.loop_tiles.clone(),
+            path_cache: self.path_cache.clone(),
+        }
+    }
 }
 
 impl<T: Tiling> LanguageState<T>
 where
     T::Edge: FollowableDirection,
 {
-    #[allow(unused)]
     pub fn step<Out: Write, In: Read>(&mut self, out: &mut Out, input: &mut In) {
+        if self.loop_detector.is_none() {
+            self.execute_step(out, input);
+            return;
+        }
+
+        let mut out = loop_detection::Watched::new(out);
+        let mut input = loop_detection::Watched::new(input);
+        let distance = self.execute_step(&mut out, &mut input);
+        if self.is_running() {
+            self.detect_loops(out.used || input.used, distance);
+        }
+    }
+
+    /// Returns how many tiles the instruction pointer moved
+    fn execute_step<Out: Write, In: Read>(&mut self, out: &mut Out, input: &mut In) -> usize {
         let instuction = self.code.get(&self.instruction_pointer);
+        let instruction_char = instuction.map(|t| t.0);
 
         let get_instruction_char_or_default =
             || stack_item::StackItem::Number(instuction.map(|t| t.0 as u32 as f64).unwrap_or(0.0));
 
         let behavior = match &mut self.mode {
-            Mode::NormalMode => {
-                if let Some((ch, Some(instruction))) = instuction {
-                    instruction.evaluate(&mut self.mode, &mut self.stack, out, input)
-                } else {
-                    InstructionPointerBehavior::Straight
+            Mode::NormalMode => self.evaluate_instruction(out, input),
+            Mode::Number(digits) => match instruction_char {
+                Some(ch) if literals::is_number_literal_character(digits, ch) => {
+                    digits.push(ch);
+                    Ok(InstructionPointerBehavior::Straight)
+                }
+                // The number ends at the first tile that isn't part of it, that tile is then
+                // evaluated like normal
+                _ => {
+                    let number = literals::parse_number_literal(digits);
+                    self.mode = Mode::NormalMode;
+                    number.and_then(|number| {
+                        instructions::current_stack(&mut self.stacks).push(number.into());
+                        self.evaluate_instruction(out, input)
+                    })
                 }
+            },
+            Mode::Escape(inner_mode, sequence) => {
+                sequence.push(instruction_char.unwrap_or('\0'));
+                match literals::resolve_escape(sequence) {
+                    Ok(Some(ch)) => {
+                        let inner_mode = std::mem::replace(inner_mode.as_mut(), Mode::NormalMode);
+                        self.mode = push_string_character(
+                            inner_mode,
+                            &mut self.stacks,
+                            stack_item::StackItem::Number(ch as u32 as f64),
+                        );
+                        Ok(InstructionPointerBehavior::Straight)
+                    }
+                    Ok(None) => Ok(InstructionPointerBehavior::Straight),
+                    Err(e) => {
+                        self.mode = Mode::NormalMode;
+                        Err(e)
+                    }
+                }
+            }
+            mode @ (Mode::CharMode | Mode::ArrayStringMode(_) | Mode::CharStringMode(_))
+                if instruction_char == Some('\\') =>
+            {
+                let inner_mode = std::mem::replace(mode, Mode::NormalMode);
+                self.mode = Mode::Escape(Box::new(inner_mode), String::new());
+                Ok(InstructionPointerBehavior::Straight)
             }
             Mode::CharMode => {
                 self.mode = Mode::NormalMode;
-                self.stack.push(get_instruction_char_or_default());
-                InstructionPointerBehavior::Straight
+                instructions::current_stack(&mut self.stacks)
+                    .push(get_instruction_char_or_default());
+                Ok(InstructionPointerBehavior::Straight)
             }
             Mode::ArrayStringMode(e) => {
                 if let Some(Instruction::StartArrayString) = instuction.and_then(|k| k.1) {
-                    self.stack.push(stack_item::StackItem::Array(e.clone()));
+                    instructions::current_stack(&mut self.stacks)
+                        .push(stack_item::StackItem::Array(e.clone()));
                     self.mode = Mode::NormalMode;
                 } else {
                     e.push(get_instruction_char_or_default());
                 };
-                InstructionPointerBehavior::Straight
+                Ok(InstructionPointerBehavior::Straight)
             }
             Mode::CharStringMode(e) => {
                 if let Some(Instruction::StartCharacterString) = instuction.and_then(|k| k.1) {
-                    self.stack.extend(e.iter().cloned());
+                    instructions::current_stack(&mut self.stacks).extend(e.iter().cloned());
                     self.mode = Mode::NormalMode;
                 } else {
                     e.push(get_instruction_char_or_default());
                 };
-                InstructionPointerBehavior::Straight
+                Ok(InstructionPointerBehavior::Straight)
             }
-            _ => InstructionPointerBehavior::Straight,
+            _ => Ok(InstructionPointerBehavior::Straight),
+        };
+
+        let behavior = match behavior {
+            Ok(behavior) => behavior,
+            Err(error) => match self.guards.pop() {
+                Some((coordinate, direction)) => {
+                    self.instruction_pointer = coordinate;
+                    self.direction = direction;
+                    instructions::current_stack(&mut self.stacks).push(error.code().into());
+                    InstructionPointerBehavior::Right
+                }
+                None => {
+                    self.mode = Mode::Stopped(RunOutcome::RuntimeError(error));
+                    return 0;
+                }
+            },
         };
 
         let next_direction = match behavior {
@@ -84,83 +257,263 @@ where
             InstructionPointerBehavior::Back => self.direction.opposite(),
         };
 
-        let next_position = self.instruction_pointer.go(next_direction).unwrap();
+        self.advance(next_direction)
+    }
+
+    /// Moves the instruction pointer to the next tile in `direction`, or straight to the next
+    /// code tile if the program is compiled. Returns how many tiles it moved.
+    fn advance(&mut self, direction: T::Edge) -> usize {
+        // Empty tiles only do nothing in normal mode, in other modes they are part of a literal
+        if let (Mode::NormalMode, Some(cache)) = (&self.mode, &self.path_cache) {
+            if let Some(path) = cache.get(&self.instruction_pointer, direction) {
+                self.instruction_pointer = path.tile.clone();
+                self.direction = path.direction;
+                return path.distance;
+            }
+        }
+
+        let next_position = self.instruction_pointer.go(direction).unwrap();
         self.instruction_pointer = next_position.0;
         self.direction = next_position.1.opposite();
+        1
+    }
+
+    fn detect_loops(&mut self, did_io: bool, distance: usize) {
+        let on_empty_tile = !self.code.contains_key(&self.instruction_pointer);
+        let Some(mut detector) = self.loop_detector.take() else {
+            return;
+        };
+
+        if let Some(length) = detector.loop_length() {
+            // The loop is run once more to find its tiles, it does no I/O so this is safe
+            self.loop_tiles.push(self.instruction_pointer.clone());
+            if self.loop_tiles.len() == length {
+                self.loop_tiles = self.loop_tiles.drain(..).unique().collect();
+                self.mode = Mode::Stopped(RunOutcome::InfiniteLoop { length });
+            }
+        } else if detector.wander(on_empty_tile, distance) {
+            self.mode = Mode::Stopped(RunOutcome::LeftProgram);
+        } else {
+            detector.visit(
+                did_io,
+                |snapshot| self.is_in_state(snapshot),
+                || self.snapshot(),
+            );
+        }
+        self.loop_detector = Some(detector);
+    }
+
+    fn snapshot(&self) -> Snapshot<T> {
+        (
+            self.instruction_pointer.clone(),
+            self.direction,
+            self.mode.clone(),
+            self.stacks.clone(),
+            self.guards.clone(),
+            self.number_format.clone(),
+        )
+    }
+
+    /// Whether the program is exactly in the saved state, the cheap parts are compared first
+    fn is_in_state(&self, snapshot: &Snapshot<T>) -> bool {
+        let (instruction_pointer, direction, mode, stacks, guards, number_format) = snapshot;
+        self.instruction_pointer == *instruction_pointer
+            && self.direction == *direction
+            && self.mode == *mode
+            && self.guards == *guards
+            && self.number_format == *number_format
+            && self.stacks.len() == stacks.len()
+            && self.stacks.iter().zip(stacks).all(|(stack, other)| {
+                stack.len() == other.len()
+                    && stack.iter().zip(other).all(|(a, b)| a.is_identical(b))
+            })
+    }
+
+    fn evaluate_instruction<Out: Write, In: Read>(
+        &mut self,
+        out: &mut Out,
+        input: &mut In,
+    ) -> Result<InstructionPointerBehavior, error::RuntimeError> {
+        match self.code.get(&self.instruction_pointer) {
+            Some((_, Some(Instruction::Guard))) => {
+                self.guards
+                    .push((self.instruction_pointer.clone(), self.direction));
+                Ok(InstructionPointerBehavior::Straight)
+            }
+            Some((_, Some(Instruction::ClearGuard))) => {
+                self.guards.pop();
+                Ok(InstructionPointerBehavior::Straight)
+            }
+            Some((_, Some(instruction))) => instruction.evaluate(
+                &mut self.mode,
+                &mut self.stacks,
+                out,
+                input,
+                &mut self.number_format,
+            ),
+            _ => Ok(InstructionPointerBehavior::Straight),
+        }
+    }
+
+    pub(crate) fn new(
+        code: HashMap<TileCoordinate<T>, (char, Option<Instruction>)>,
+        source_map: source_map::SourceMap<T>,
+        (instruction_pointer, direction): (TileCoordinate<T>, T::Edge),
+    ) -> Self {
+        LanguageState {
+            code,
+            entry: (instruction_pointer.clone(), direction),
+            instruction_pointer,
+            direction,
+            stacks: vec![vec![]],
+            mode: Mode::NormalMode,
+            guards: vec![],
+            number_format: format::NumberFormat::default(),
+            source_map,
+            loop_detector: None,
+            loop_tiles: vec![],
+            path_cache: None,
+        }
     }
 
     #[allow(unused)]
-    pub fn new_from_string(source_code: String) -> Result<Self, error::ParseError>
+    pub fn new_from_string(source_code: String) -> Result<Self, error::ParseError<T::Tile>>
     where
-        TileCoordinate<T>: FromStr,
+        T::Tile: TryFrom<char, Error = char>,
         T::Edge: FromStr,
     {
-        let mut program = HashMap::<TileCoordinate<T>, (char, Option<Instruction>)>::new();
-        for (line_number, line) in source_code.lines().enumerate() {
-            let Some((coordinate, code)) = line.split_once(':') else {
-                return Err(error::ParseError {
-                    line: line_number,
-                    column: 0,
-                    kind: error::ParseErrorKind::InvalidPrefixError,
-                });
-            };
-
-            let (coordinate, mut direction) = match coordinate.split_once('-') {
-                Some((coodinate, direction)) => (
-                    coodinate,
-                    direction.parse().map_err(|e| error::ParseError {
-                        line: line_number,
-                        column: coodinate.len(),
-                        kind: error::ParseErrorKind::BadDirectionError,
-                    })?,
-                ),
-                None => (coordinate, T::Edge::VARIANTS[0]),
-            };
-
-            let mut coordinate: TileCoordinate<T> = match coordinate.parse() {
-                Ok(coord) => coord,
-                Err(e) => {
-                    return Err(error::ParseError {
-                        line: line_number,
-                        column: 0,
-                        kind: error::ParseErrorKind::BadCoordinateError,
-                    })
-                }
-            };
-
-            for (index, char) in code.chars().enumerate() {
-                while program.contains_key(&coordinate) {
-                    (coordinate, direction) = match coordinate.go(direction) {
-                        Ok((coordinate, direction)) => (coordinate, direction.opposite()),
-                        Err(e) => {
-                            return Err(error::ParseError {
-                                line: line_number,
-                                column: index,
-                                kind: error::ParseErrorKind::BadCoordinateError,
-                            })
-                        }
-                    };
-                }
+        Ok(Self::parse(&source_code, &parser::ParseOptions::default())?.state)
+    }
 
-                program.insert(coordinate.clone(), (char, Instruction::from_char(char)));
-            }
-        }
+    /// Reads a program in the JSON format, see [`json`]
+    pub fn new_from_json(json: &str) -> Result<Self, error::JsonError<T::Tile>>
+    where
+        T::Tile: TryFrom<char, Error = char>,
+        T::Edge: FromStr,
+    {
+        json::read(json)
+    }
 
-        Ok(LanguageState {
-            code: program,
-            instruction_pointer: TileCoordinate::new(vec![]).unwrap(),
-            direction: T::Edge::VARIANTS[0],
-            stack: vec![],
-            mode: Mode::NormalMode,
-        })
+    /// Parses a program, also returning any warnings about the layout of the code
+    pub fn parse(
+        source_code: &str,
+        options: &parser::ParseOptions,
+    ) -> Result<parser::ParsedProgram<T>, error::ParseError<T::Tile>>
+    where
+        T::Tile: TryFrom<char, Error = char>,
+        T::Edge: FromStr,
+    {
+        parser::Parser::new(options).parse(source_code)
     }
 
     pub fn is_running(&self) -> bool {
         match self.mode {
-            Mode::Stopped => false,
+            Mode::Stopped(_) => false,
             _ => true,
         }
     }
+
+    pub fn get_number_format(&self) -> &format::NumberFormat {
+        &self.number_format
+    }
+
+    pub fn set_number_format(&mut self, number_format: format::NumberFormat) {
+        self.number_format = number_format;
+    }
+
+    /// The character on a tile, `None` for empty tiles
+    pub fn get_char(&self, tile: &TileCoordinate<T>) -> Option<char> {
+        self.code.get(tile).map(|(character, _)| *character)
+    }
+
+    /// Every tile that has a character on it
+    pub(crate) fn get_code_tiles(&self) -> impl Iterator<Item = &TileCoordinate<T>> {
+        self.code.keys()
+    }
+
+    /// The tile and direction the program starts at, even after it ran
+    pub fn get_entry(&self) -> (&TileCoordinate<T>, T::Edge) {
+        (&self.entry.0, self.entry.1)
+    }
+
+    pub fn get_instruction_pointer(&self) -> &TileCoordinate<T> {
+        &self.instruction_pointer
+    }
+
+    pub fn get_direction(&self) -> T::Edge {
+        self.direction
+    }
+
+    /// Links the tiles of the program back to the source code it was parsed from
+    pub fn get_source_map(&self) -> &source_map::SourceMap<T> {
+        &self.source_map
+    }
+
+    /// How the program terminated, `None` while it is still running
+    pub fn get_outcome(&self) -> Option<RunOutcome> {
+        match self.mode {
+            Mode::Stopped(outcome) => Some(outcome),
+            _ => None,
+        }
+    }
+
+    /// Precomputes the paths between code tiles, so that from then on the instruction pointer
+    /// jumps over empty tiles in a single step instead of visiting each of them. Makes long
+    /// running programs a lot faster, but [`Self::step`] no longer stops on every tile.
+    pub fn compile(&mut self) {
+        self.path_cache = Some(path_cache::PathCache::new(&self.code));
+    }
+
+    /// Stops the program with [`RunOutcome::InfiniteLoop`] or [`RunOutcome::LeftProgram`] when
+    /// it can never finish. Off by default, as every step compares the state to a saved one, and
+    /// a loop is only found after it went around up to a few times.
+    pub fn set_loop_detection(&mut self, detection: Option<loop_detection::LoopDetection>) {
+        self.loop_detector = detection.map(loop_detection::LoopDetector::new);
+        self.loop_tiles.clear();
+    }
+
+    /// Runs the program on `input` with loop detection until it stops or has taken `max_steps`
+    /// steps, returning what it wrote. Used to compare programs, see [`crate::minimize`] and
+    /// [`crate::bytecode::differential`].
+    pub(crate) fn run_detecting_loops(
+        &mut self,
+        mut input: &[u8],
+        detection: loop_detection::LoopDetection,
+        max_steps: usize,
+    ) -> Vec<u8> {
+        self.set_loop_detection(Some(detection));
+        let mut output = vec![];
+        for _ in 0..max_steps {
+            if !self.is_running() {
+                break;
+            }
+            self.step(&mut output, &mut input);
+        }
+        output
+    }
+
+    /// The tiles of the loop the program was stopped in, in the order they are visited, empty
+    /// unless the outcome is [`RunOutcome::InfiniteLoop`]
+    pub fn get_loop_tiles(&self) -> &[TileCoordinate<T>] {
+        &self.loop_tiles
+    }
+}
+
+impl<T: Tiling> LanguageState<T>
+where
+    T::Tile: Into<char>,
+    T::Edge: FollowableDirection + std::fmt::Display,
+{
+    /// Writes the code back out as source, in a canonical form that parses to the same tiles and
+    /// entry point
+    pub fn serialize(&self) -> String {
+        serialize::serialize(self)
+    }
+
+    /// Writes the program in the JSON format, see [`json`]
+    pub fn to_json(&self) -> String {
+        json::write(self)
+    }
 }
 
 impl<T: Tiling> LanguageState<T>
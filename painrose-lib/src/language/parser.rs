@@ -0,0 +1,622 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use strum::VariantArray;
+
+use crate::geometry::tile_coordinate::{
+    CoordinateParsingError, CoordinateParsingErrorKind, CoordinateTraversalError, TileCoordinate,
+};
+use crate::geometry::tiling::Tiling;
+
+use super::error::{ParseError, ParseErrorKind, ParseWarning};
+use super::instructions::Instruction;
+use super::source_map::{SourceLocation, SourceMap};
+use super::{FollowableDirection, LanguageState};
+
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+    /// Fail when a character has to be moved because its tile is already used by another
+    /// character, instead of only warning about it
+    pub collisions_are_errors: bool,
+    /// The directory `!include` paths are relative to, the working directory by default
+    pub base_directory: Option<PathBuf>,
+}
+
+/// Information from the `!` headers of a program
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProgramMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// The name of the tiling the program is written for
+    pub tiling: Option<String>,
+    /// The prefix of the tile the program starts on, the origin tile heading north by default
+    pub entry: Option<String>,
+    /// The language version the program requires
+    pub version: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ParsedProgram<T: Tiling>
+where
+    T::Edge: FollowableDirection,
+{
+    pub state: LanguageState<T>,
+    pub metadata: ProgramMetadata,
+    pub warnings: Vec<ParseWarning<T>>,
+}
+
+/// A tile together with the direction code continues in from it
+type Position<T> = (TileCoordinate<T>, <T as Tiling>::Edge);
+
+/// Where a line of code was laid out
+struct LineLayout<T: Tiling> {
+    start: Position<T>,
+    /// The tile of the last character and the direction the line continues in, `None` for
+    /// lines without code
+    end: Option<Position<T>>,
+}
+
+impl<T: Tiling> LineLayout<T>
+where
+    T::Edge: FollowableDirection,
+{
+    /// The tile the next character would have been placed on
+    fn after_end(&self) -> Result<Position<T>, CoordinateTraversalError<T::Tile>> {
+        match &self.end {
+            Some((coordinate, direction)) => coordinate
+                .go(*direction)
+                .map(|(coordinate, direction)| (coordinate, direction.opposite())),
+            None => Ok(self.start.clone()),
+        }
+    }
+}
+
+pub(super) struct Parser<'a, T: Tiling> {
+    options: &'a ParseOptions,
+    code: HashMap<TileCoordinate<T>, (char, Option<Instruction>)>,
+    source_map: SourceMap<T>,
+    warnings: Vec<ParseWarning<T>>,
+    anchors: HashMap<String, TileCoordinate<T>>,
+    lines: HashMap<usize, LineLayout<T>>,
+    previous_line: Option<usize>,
+    metadata: ProgramMetadata,
+    /// The line and column of the value of the `!entry` header
+    entry_location: SourceLocation,
+    /// The files that are being included, to detect cycles
+    include_chain: Vec<PathBuf>,
+}
+
+impl<'a, T: Tiling> Parser<'a, T>
+where
+    T::Tile: TryFrom<char, Error = char>,
+    T::Edge: FollowableDirection + FromStr,
+{
+    pub(super) fn new(options: &'a ParseOptions) -> Self {
+        Parser {
+            options,
+            code: HashMap::new(),
+            source_map: SourceMap::default(),
+            warnings: vec![],
+            anchors: HashMap::new(),
+            lines: HashMap::new(),
+            previous_line: None,
+            metadata: ProgramMetadata::default(),
+            entry_location: SourceLocation { line: 0, column: 0 },
+            include_chain: vec![],
+        }
+    }
+
+    pub(super) fn parse(
+        mut self,
+        source_code: &str,
+    ) -> Result<ParsedProgram<T>, ParseError<T::Tile>> {
+        self.parse_lines(source_code)?;
+
+        let entry = match &self.metadata.entry {
+            Some(prefix) => {
+                let SourceLocation { line, column } = self.entry_location;
+                self.resolve_prefix(line, prefix).map_err(|mut e| {
+                    e.column += column;
+                    e
+                })?
+            }
+            None => (TileCoordinate::new(vec![]).unwrap(), T::Edge::VARIANTS[0]),
+        };
+
+        Ok(ParsedProgram {
+            state: LanguageState::new(self.code, self.source_map, entry),
+            metadata: self.metadata,
+            warnings: self.warnings,
+        })
+    }
+
+    fn parse_lines(&mut self, source_code: &str) -> Result<(), ParseError<T::Tile>> {
+        for (line_number, line) in source_code.lines().enumerate() {
+            self.parse_line(line_number, line)?;
+        }
+        Ok(())
+    }
+
+    fn parse_line(&mut self, line_number: usize, line: &str) -> Result<(), ParseError<T::Tile>> {
+        if line.trim().is_empty() || line.trim_start().starts_with("//") {
+            return Ok(());
+        }
+
+        if let Some(header) = line.strip_prefix('!') {
+            return self.parse_header(line_number, header);
+        }
+
+        if let Some((name, value)) = line
+            .strip_prefix('@')
+            .and_then(|definition| definition.split_once('='))
+            .filter(|(name, _)| is_anchor_name(name))
+        {
+            return self.define_anchor(line_number, name, value);
+        }
+
+        let Some((prefix, code)) = line.split_once(':') else {
+            return Err(ParseError {
+                line: line_number,
+                column: 0,
+                length: line.chars().count(),
+                kind: ParseErrorKind::InvalidPrefixError,
+            });
+        };
+        let code_column = prefix.chars().count() + 1;
+
+        let (coordinate, direction) = self.resolve_prefix(line_number, prefix)?;
+        self.place_code(line_number, code_column, coordinate, direction, code)
+    }
+
+    /// Finds the first tile and direction of a line from a prefix like `AEDB-e`, `@loop`, `+`
+    /// or `#3/nne-w`
+    fn resolve_prefix(
+        &self,
+        line_number: usize,
+        prefix: &str,
+    ) -> Result<Position<T>, ParseError<T::Tile>> {
+        let (start, direction) = match prefix.split_once('-') {
+            Some((start, direction)) => (
+                start,
+                Some(direction.parse().map_err(|_| ParseError {
+                    line: line_number,
+                    column: start.chars().count() + 1,
+                    length: direction.chars().count(),
+                    kind: ParseErrorKind::BadDirectionError(direction.to_string()),
+                })?),
+            ),
+            None => (prefix, None),
+        };
+        let (start, moves) = start.split_once('/').unwrap_or((start, ""));
+
+        let (mut coordinate, mut heading) = if let Some(name) = start.strip_prefix('@') {
+            let coordinate = self.anchors.get(name).cloned().ok_or_else(|| ParseError {
+                line: line_number,
+                column: 0,
+                length: start.chars().count(),
+                kind: ParseErrorKind::UndefinedAnchorError(name.to_string()),
+            })?;
+            (coordinate, T::Edge::VARIANTS[0])
+        } else if let Some(reference) = start.strip_prefix('#') {
+            self.get_line(line_number, 1, reference)?.start.clone()
+        } else if start == "+" {
+            let previous_line = self.previous_line.ok_or(ParseError {
+                line: line_number,
+                column: 0,
+                length: 1,
+                kind: ParseErrorKind::NoPreviousLineError,
+            })?;
+            self.lines[&previous_line]
+                .after_end()
+                .map_err(|e| ParseError {
+                    line: line_number,
+                    column: 0,
+                    length: 1,
+                    kind: ParseErrorKind::TraversalError(e),
+                })?
+        } else {
+            (
+                parse_coordinate(line_number, 0, start)?,
+                T::Edge::VARIANTS[0],
+            )
+        };
+
+        let moves_column = start.chars().count() + 1;
+        for (index, edge) in moves.chars().enumerate() {
+            let error = |kind| ParseError {
+                line: line_number,
+                column: moves_column + index,
+                length: 1,
+                kind,
+            };
+            let edge: T::Edge = edge
+                .to_string()
+                .parse()
+                .map_err(|_| error(ParseErrorKind::BadDirectionError(edge.to_string())))?;
+            let (next, entry) = coordinate
+                .go(edge)
+                .map_err(|e| error(ParseErrorKind::TraversalError(e)))?;
+            coordinate = next;
+            heading = entry.opposite();
+        }
+
+        Ok((coordinate, direction.unwrap_or(heading)))
+    }
+
+    /// Handles a line like `!title Hello World`, the `!` is already removed
+    fn parse_header(
+        &mut self,
+        line_number: usize,
+        header: &str,
+    ) -> Result<(), ParseError<T::Tile>> {
+        let (name, value) = header
+            .split_once(char::is_whitespace)
+            .unwrap_or((header, ""));
+        let value = value.trim_start();
+        let value_column = 1 + header.chars().count() - value.chars().count();
+        let value = value.trim_end();
+
+        let error = |column, length, kind| ParseError {
+            line: line_number,
+            column,
+            length,
+            kind,
+        };
+        let header_error = |kind| error(0, name.chars().count() + 1, kind);
+        let value_error = |kind| error(value_column, value.chars().count(), kind);
+
+        if name == "include" {
+            let error = |kind| error(value_column, value.chars().count(), kind);
+            let [path, level, supertile] = value.split_whitespace().collect::<Vec<_>>()[..] else {
+                return Err(error(ParseErrorKind::BadHeaderValueError(
+                    value.to_string(),
+                )));
+            };
+            let level = level
+                .parse()
+                .map_err(|_| error(ParseErrorKind::BadHeaderValueError(level.to_string())))?;
+            let location = SourceLocation {
+                line: line_number,
+                column: value_column,
+            };
+            return self
+                .include(path, level, supertile, location)
+                .map_err(error);
+        }
+
+        let ProgramMetadata {
+            title,
+            author,
+            tiling,
+            entry,
+            version,
+        } = &mut self.metadata;
+        let is_set = match name {
+            "title" => title.replace(value.to_string()).is_some(),
+            "author" => author.replace(value.to_string()).is_some(),
+            "tiling" => {
+                if value != T::NAME {
+                    return Err(value_error(ParseErrorKind::WrongTilingError {
+                        found: value.to_string(),
+                        expected: T::NAME,
+                    }));
+                }
+                tiling.replace(value.to_string()).is_some()
+            }
+            "entry" => {
+                self.entry_location = SourceLocation {
+                    line: line_number,
+                    column: value_column,
+                };
+                entry.replace(value.to_string()).is_some()
+            }
+            "version" => {
+                let number: u32 = value.parse().map_err(|_| {
+                    value_error(ParseErrorKind::BadHeaderValueError(value.to_string()))
+                })?;
+                // The version changes how code is read, so it can't change after the first line
+                if self.previous_line.is_some() {
+                    return Err(header_error(ParseErrorKind::LateVersionError));
+                }
+                if number > super::LANGUAGE_VERSION {
+                    return Err(value_error(ParseErrorKind::UnsupportedVersionError(number)));
+                }
+                version.replace(number).is_some()
+            }
+            _ => {
+                return Err(header_error(ParseErrorKind::UnknownHeaderError(
+                    name.to_string(),
+                )))
+            }
+        };
+
+        if is_set {
+            return Err(header_error(ParseErrorKind::DuplicateHeaderError(
+                name.to_string(),
+            )));
+        }
+        Ok(())
+    }
+
+    /// Parses another file and places its code in a supertile, `supertile` lists the tiles of the
+    /// supertile starting at `level`. The code of the file has to fit inside the origin supertile
+    /// at that level. Only the code is used, the headers of the file are ignored and its anchors
+    /// only exist inside it. The source map links the included tiles to `location`, the value of
+    /// the `!include` header.
+    fn include(
+        &mut self,
+        path: &str,
+        level: usize,
+        supertile: &str,
+        location: SourceLocation,
+    ) -> Result<(), ParseErrorKind<T::Tile>> {
+        let supertile = supertile
+            .chars()
+            .enumerate()
+            .map(|(index, tile)| {
+                T::Tile::try_from(tile).map_err(|tile| {
+                    ParseErrorKind::BadCoordinateError(CoordinateParsingError {
+                        index,
+                        kind: CoordinateParsingErrorKind::ParseError(tile),
+                    })
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let full_path = match &self.options.base_directory {
+            Some(directory) => directory.join(path),
+            None => PathBuf::from(path),
+        };
+        let canonical_path = full_path.canonicalize().unwrap_or(full_path.clone());
+        if self.include_chain.contains(&canonical_path) {
+            return Err(ParseErrorKind::IncludeCycleError(path.to_string()));
+        }
+        let source_code = std::fs::read_to_string(&full_path)
+            .map_err(|e| ParseErrorKind::IncludeError(e.to_string()))?;
+
+        let options = ParseOptions {
+            base_directory: full_path.parent().map(PathBuf::from),
+            ..self.options.clone()
+        };
+        let mut included = Parser::<T>::new(&options);
+        included.include_chain = self.include_chain.clone();
+        included.include_chain.push(canonical_path);
+        included
+            .parse_lines(&source_code)
+            .map_err(|error| match error.kind {
+                // The cycle starts at this header, so that is where it is reported
+                ParseErrorKind::IncludeCycleError(path) => ParseErrorKind::IncludeCycleError(path),
+                _ => ParseErrorKind::IncludedFileError {
+                    path: path.to_string(),
+                    error: Box::new(error),
+                },
+            })?;
+        for warning in included.warnings.drain(..) {
+            self.warnings.push(ParseWarning::IncludedFileWarning {
+                location,
+                path: path.to_string(),
+                warning: Box::new(warning),
+            });
+        }
+
+        if included
+            .code
+            .keys()
+            .any(|coordinate| coordinate.len() > level)
+        {
+            return Err(ParseErrorKind::IncludeTooLargeError(level));
+        }
+
+        for (coordinate, instruction) in included.code {
+            let coordinate = coordinate
+                .mount(level, &supertile)
+                .map_err(ParseErrorKind::TraversalError)?;
+
+            if self.code.contains_key(&coordinate) {
+                let occupied_by = self.source_map.get_location(&coordinate);
+                if self.options.collisions_are_errors {
+                    return Err(ParseErrorKind::CollisionError(occupied_by));
+                }
+                // The included code has no line to move along, so the character is left out
+                self.warnings.push(ParseWarning::IncludeCollision {
+                    location,
+                    tile: coordinate,
+                    occupied_by,
+                });
+                continue;
+            }
+            self.source_map
+                .insert_included(coordinate.clone(), location);
+            self.code.insert(coordinate, instruction);
+        }
+
+        Ok(())
+    }
+
+    /// Handles a line like `@name=AEDB` or `@name=+3`, the latter being the tile after the end
+    /// of line 3
+    fn define_anchor(
+        &mut self,
+        line_number: usize,
+        name: &str,
+        value: &str,
+    ) -> Result<(), ParseError<T::Tile>> {
+        if self.anchors.contains_key(name) {
+            return Err(ParseError {
+                line: line_number,
+                column: 0,
+                length: name.chars().count() + 1,
+                kind: ParseErrorKind::DuplicateAnchorError(name.to_string()),
+            });
+        }
+
+        let value_column = name.chars().count() + 2;
+        let coordinate = match value.strip_prefix('+') {
+            Some(reference) => {
+                let layout = self.get_line(line_number, value_column + 1, reference)?;
+                layout
+                    .after_end()
+                    .map_err(|e| ParseError {
+                        line: line_number,
+                        column: value_column,
+                        length: value.chars().count(),
+                        kind: ParseErrorKind::TraversalError(e),
+                    })?
+                    .0
+            }
+            None => parse_coordinate(line_number, value_column, value)?,
+        };
+
+        self.anchors.insert(name.to_string(), coordinate);
+        Ok(())
+    }
+
+    /// Looks up an earlier line by its line number as written in the source, counting from 1
+    fn get_line(
+        &self,
+        line_number: usize,
+        column: usize,
+        reference: &str,
+    ) -> Result<&LineLayout<T>, ParseError<T::Tile>> {
+        let error = || ParseError {
+            line: line_number,
+            column,
+            length: reference.chars().count(),
+            kind: ParseErrorKind::BadLineReferenceError(reference.to_string()),
+        };
+
+        let referenced_line = reference
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .ok_or_else(error)?;
+        self.lines.get(&referenced_line).ok_or_else(error)
+    }
+
+    /// Lays out code in a straight line, walking past any tiles that are already used. From
+    /// language version 2 on, inside the code `@<` and `@>` turn the line, `@n`, `@e`, `@s` and
+    /// `@w` point it in a direction and `@@` places an `@`. Before that `@` is a normal character.
+    fn place_code(
+        &mut self,
+        line_number: usize,
+        code_column: usize,
+        mut coordinate: TileCoordinate<T>,
+        mut direction: T::Edge,
+        code: &str,
+    ) -> Result<(), ParseError<T::Tile>> {
+        let start = (coordinate.clone(), direction);
+        let mut end = None;
+
+        let has_directives = self.metadata.version.is_some_and(|version| version >= 2);
+        let mut chars = code.chars().enumerate();
+        while let Some((index, mut char)) = chars.next() {
+            if char == '@' && has_directives {
+                let directive = chars.next().map(|(_, directive)| directive);
+                match directive {
+                    Some('@') => {}
+                    Some('<') => direction = direction.turn_left(),
+                    Some('>') => direction = direction.turn_right(),
+                    _ => {
+                        direction = directive
+                            .and_then(|directive| directive.to_string().parse().ok())
+                            .ok_or_else(|| ParseError {
+                                line: line_number,
+                                column: code_column + index,
+                                length: if directive.is_some() { 2 } else { 1 },
+                                kind: ParseErrorKind::BadDirectiveError(
+                                    directive.map(String::from).unwrap_or_default(),
+                                ),
+                            })?
+                    }
+                }
+                if directive != Some('@') {
+                    continue;
+                }
+                char = '@';
+            }
+
+            let location = SourceLocation {
+                line: line_number,
+                column: code_column + index,
+            };
+            let step = |coordinate: &TileCoordinate<T>, direction: T::Edge| {
+                coordinate
+                    .go(direction)
+                    .map(|(coordinate, direction)| (coordinate, direction.opposite()))
+                    .map_err(|e| ParseError {
+                        line: location.line,
+                        column: location.column,
+                        length: 1,
+                        kind: ParseErrorKind::TraversalError(e),
+                    })
+            };
+
+            if end.is_some() {
+                (coordinate, direction) = step(&coordinate, direction)?;
+            }
+
+            if self.code.contains_key(&coordinate) {
+                let occupied_by = self.source_map.get_location(&coordinate);
+                if self.options.collisions_are_errors {
+                    return Err(ParseError {
+                        line: location.line,
+                        column: location.column,
+                        length: 1,
+                        kind: ParseErrorKind::CollisionError(occupied_by),
+                    });
+                }
+
+                let tile = coordinate.clone();
+                while self.code.contains_key(&coordinate) {
+                    (coordinate, direction) = step(&coordinate, direction)?;
+                }
+
+                self.warnings.push(ParseWarning::Collision {
+                    location,
+                    tile,
+                    occupied_by,
+                    placed_at: coordinate.clone(),
+                });
+            }
+
+            self.code
+                .insert(coordinate.clone(), (char, Instruction::from_char(char)));
+            self.source_map.insert(coordinate.clone(), location);
+            end = Some(coordinate.clone());
+        }
+
+        let end = end.map(|coordinate| (coordinate, direction));
+        self.lines.insert(line_number, LineLayout { start, end });
+        self.previous_line = Some(line_number);
+        Ok(())
+    }
+}
+
+fn is_anchor_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Parses a coordinate that starts at `column` of the line
+fn parse_coordinate<T: Tiling>(
+    line_number: usize,
+    column: usize,
+    coordinate: &str,
+) -> Result<TileCoordinate<T>, ParseError<T::Tile>>
+where
+    T::Tile: TryFrom<char, Error = char>,
+{
+    coordinate
+        .parse()
+        .map_err(|e: CoordinateParsingError<T::Tile>| ParseError {
+            line: line_number,
+            column: column + e.index,
+            length: match e.kind {
+                CoordinateParsingErrorKind::TraversalError(_) => {
+                    coordinate.chars().count().min(e.index + 2) - e.index
+                }
+                CoordinateParsingErrorKind::ParseError(_) => 1,
+            },
+            kind: ParseErrorKind::BadCoordinateError(e),
+        })
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use strum::VariantArray;
+
+use crate::geometry::tile_coordinate::TileCoordinate;
+use crate::geometry::tiling::Tiling;
+
+use super::FollowableDirection;
+
+/// How far a path is followed over empty tiles before it is considered to escape the program
+const MAX_DISTANCE: usize = 256;
+
+/// Where the instruction pointer ends up after leaving a code tile in some direction
+#[derive(Debug)]
+pub(super) struct Path<T: Tiling> {
+    pub(super) tile: TileCoordinate<T>,
+    /// The direction the instruction pointer is going in when it arrives
+    pub(super) direction: T::Edge,
+    /// How many tiles the instruction pointer moves to get there
+    pub(super) distance: usize,
+}
+
+/// The paths between the code tiles of a program, so the interpreter can jump over empty tiles
+/// instead of walking through them one at a time.
+///
+/// A path that reaches no code within [`MAX_DISTANCE`] tiles escapes the program, and ends at
+/// the last tile followed instead.
+#[derive(Debug)]
+pub(super) struct PathCache<T: Tiling> {
+    /// The paths leaving each code tile, in the order of `T::Edge::VARIANTS`
+    paths: HashMap<TileCoordinate<T>, Vec<Path<T>>>,
+}
+
+impl<T: Tiling> Clone for Path<T> {
+    fn clone(&self) -> Self {
+        Path {
+            tile: self.tile.clone(),
+            direction: self.direction,
+            distance: self.distance,
+        }
+    }
+}
+
+impl<T: Tiling> Clone for PathCache<T> {
+    fn clone(&self) -> Self {
+        PathCache {
+            paths: self.paths.clone(),
+        }
+    }
+}
+
+impl<T: Tiling> PathCache<T>
+where
+    T::Edge: FollowableDirection,
+{
+    pub(super) fn new<V>(code: &HashMap<TileCoordinate<T>, V>) -> Self {
+        let paths = code
+            .keys()
+            .map(|start| {
+                let paths = T::Edge::VARIANTS
+                    .iter()
+                    .map(|&edge| {
+                        let mut path = Path {
+                            tile: start.clone(),
+                            direction: edge,
+                            distance: 0,
+                        };
+                        loop {
+                            let (tile, entry) = path.tile.go(path.direction).unwrap();
+                            path.tile = tile;
+                            path.direction = entry.opposite();
+                            path.distance += 1;
+
+                            if code.contains_key(&path.tile) || path.distance >= MAX_DISTANCE {
+                                break path;
+                            }
+                        }
+                    })
+                    .collect();
+                (start.clone(), paths)
+            })
+            .collect();
+
+        PathCache { paths }
+    }
+
+    /// The path leaving `tile` in `direction`, `None` if `tile` isn't a code tile
+    pub(super) fn get(&self, tile: &TileCoordinate<T>, direction: T::Edge) -> Option<&Path<T>> {
+        let index = T::Edge::VARIANTS
+            .iter()
+            .position(|edge| *edge == direction)?;
+        self.paths.get(tile).map(|paths| &paths[index])
+    }
+}
@@ -0,0 +1,155 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt::Display;
+
+use strum::VariantArray;
+
+use crate::geometry::tile_coordinate::TileCoordinate;
+use crate::geometry::tiling::Tiling;
+
+use super::{FollowableDirection, LanguageState};
+
+/// Follows a straight line from `start` over tiles that are still `remaining`
+fn get_run<T: Tiling>(
+    start: &TileCoordinate<T>,
+    direction: T::Edge,
+    remaining: &HashSet<&TileCoordinate<T>>,
+) -> Vec<TileCoordinate<T>>
+where
+    T::Edge: FollowableDirection,
+{
+    let mut run = vec![start.clone()];
+    let mut visited = HashSet::from([start.clone()]);
+    let mut direction = direction;
+
+    while let Ok((next, entry)) = run[run.len() - 1].go(direction) {
+        if !remaining.contains(&next) || !visited.insert(next.clone()) {
+            break;
+        }
+        run.push(next);
+        direction = entry.opposite();
+    }
+
+    run
+}
+
+/// Whether a straight line in `direction` through `tile` comes from another remaining tile, in
+/// which case the run should rather start at that tile
+fn has_predecessor<T: Tiling>(
+    tile: &TileCoordinate<T>,
+    direction: T::Edge,
+    remaining: &HashSet<&TileCoordinate<T>>,
+) -> bool
+where
+    T::Edge: FollowableDirection,
+{
+    tile.go(direction.opposite())
+        .is_ok_and(|(previous, _)| &previous != tile && remaining.contains(&previous))
+}
+
+/// How good a run from a tile in a direction is, compared the same way for every candidate:
+/// runs that can't be extended backwards first, then longer runs, then the tile that comes first
+/// and the first direction
+type Rank = (bool, usize, Reverse<usize>, Reverse<usize>);
+
+fn rank<T: Tiling>(
+    tile: &TileCoordinate<T>,
+    (tile_index, direction_index): (usize, usize),
+    remaining: &HashSet<&TileCoordinate<T>>,
+) -> Rank
+where
+    T::Edge: FollowableDirection,
+{
+    let direction = T::Edge::VARIANTS[direction_index];
+    (
+        !has_predecessor(tile, direction, remaining),
+        get_run(tile, direction, remaining).len(),
+        Reverse(tile_index),
+        Reverse(direction_index),
+    )
+}
+
+/// Writes the code as a list of straight runs. Runs are picked greedily, longest first,
+/// preferring runs that can't be extended backwards, which usually gives few lines but not always
+/// the fewest. Every run only covers tiles no earlier run used, so parsing the output never moves
+/// a character.
+pub(super) fn serialize<T: Tiling>(state: &LanguageState<T>) -> String
+where
+    T::Tile: Into<char>,
+    T::Edge: FollowableDirection + Display,
+{
+    let default_direction = T::Edge::VARIANTS[0];
+
+    let mut tiles: Vec<_> = state.code.keys().collect();
+    tiles.sort_by_cached_key(|tile| {
+        let name = tile.to_string();
+        (name.len(), name)
+    });
+    let indices: HashMap<_, _> = tiles
+        .iter()
+        .enumerate()
+        .map(|(index, &tile)| (tile, index))
+        .collect();
+    let mut remaining: HashSet<_> = tiles.iter().copied().collect();
+
+    let mut lines = vec![];
+    let (entry_tile, entry_direction) = &state.entry;
+    if *entry_tile != TileCoordinate::new(vec![]).unwrap() || *entry_direction != default_direction
+    {
+        lines.push(format!("!entry {entry_tile}-{entry_direction}"));
+    }
+
+    // Removing tiles only makes runs shorter, except that a run can become a start when the tile
+    // before it goes, so those get ranked again when it happens. Every other rank in the heap is at
+    // least as good as the run is now, and a popped rank that is out of date is ranked again.
+    let mut candidates = BinaryHeap::new();
+    for (tile_index, tile) in tiles.iter().enumerate() {
+        for direction_index in 0..T::Edge::VARIANTS.len() {
+            let key = (tile_index, direction_index);
+            candidates.push((rank(tile, key, &remaining), key));
+        }
+    }
+
+    while let Some((best, key @ (tile_index, direction_index))) = candidates.pop() {
+        let tile = tiles[tile_index];
+        if !remaining.contains(tile) {
+            continue;
+        }
+        let current = rank(tile, key, &remaining);
+        if current != best {
+            candidates.push((current, key));
+            continue;
+        }
+
+        let direction = T::Edge::VARIANTS[direction_index];
+        let run = get_run(tile, direction, &remaining);
+        for tile in &run {
+            remaining.remove(tile);
+        }
+        for tile in &run {
+            for &direction in T::Edge::VARIANTS {
+                let Ok((neighbour, _)) = tile.go(direction) else {
+                    continue;
+                };
+                let Some(&neighbour) = remaining.get(&neighbour) else {
+                    continue;
+                };
+                for direction_index in 0..T::Edge::VARIANTS.len() {
+                    let key = (indices[neighbour], direction_index);
+                    candidates.push((rank(neighbour, key, &remaining), key));
+                }
+            }
+        }
+
+        let mut line = run[0].to_string();
+        if run.len() > 1 && direction != default_direction {
+            line.push_str(&format!("-{direction}"));
+        }
+        line.push(':');
+        // Without a `!version` header an `@` in the code is a normal character
+        line.extend(run.iter().map(|tile| state.code[tile].0));
+        lines.push(line);
+    }
+
+    lines.into_iter().map(|line| line + "\n").collect()
+}
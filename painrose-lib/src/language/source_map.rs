@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use crate::geometry::{tile_coordinate::TileCoordinate, tiling::Tiling};
+
+/// A position in the source code, both the line and the column start at 0
+///
+/// The column counts characters, not bytes.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct SourceLocation {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Links every tile of a program to the character in the source code it came from
+#[derive(Debug)]
+pub struct SourceMap<T: Tiling> {
+    locations: HashMap<TileCoordinate<T>, SourceLocation>,
+    tiles: HashMap<SourceLocation, TileCoordinate<T>>,
+}
+
+impl<T: Tiling> Clone for SourceMap<T> {
+    fn clone(&self) -> Self {
+        SourceMap {
+            locations: self.locations.clone(),
+            tiles: self.tiles.clone(),
+        }
+    }
+}
+
+impl<T: Tiling> Default for SourceMap<T> {
+    fn default() -> Self {
+        SourceMap {
+            locations: HashMap::new(),
+            tiles: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Tiling> SourceMap<T> {
+    pub(crate) fn insert(&mut self, coordinate: TileCoordinate<T>, location: SourceLocation) {
+        self.tiles.insert(location, coordinate.clone());
+        self.locations.insert(coordinate, location);
+    }
+
+    /// Links a tile placed by an `!include` to the header, many tiles share the location so it
+    /// doesn't lead back to any of them
+    pub(crate) fn insert_included(
+        &mut self,
+        coordinate: TileCoordinate<T>,
+        header: SourceLocation,
+    ) {
+        self.locations.insert(coordinate, header);
+    }
+
+    /// The location in the source code of the character placed on a tile, or of the `!include`
+    /// header that placed it
+    pub fn get_location(&self, coordinate: &TileCoordinate<T>) -> Option<SourceLocation> {
+        self.locations.get(coordinate).copied()
+    }
+
+    /// The tile the character at a location in the source code was placed on
+    pub fn get_tile(&self, location: SourceLocation) -> Option<&TileCoordinate<T>> {
+        self.tiles.get(&location)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&TileCoordinate<T>, &SourceLocation)> {
+        self.locations.iter()
+    }
+}
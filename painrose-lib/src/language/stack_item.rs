@@ -1,5 +1,8 @@
 use itertools::{EitherOrBoth, Itertools};
+use std::convert::Infallible;
 use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use super::error::RuntimeError;
 #[derive(Clone, PartialEq, Debug)]
 pub enum StackItem {
     Number(f64),
@@ -7,6 +10,17 @@ pub enum StackItem {
 }
 
 impl StackItem {
+    /// Compares numbers by their bits, so `0.0` and `-0.0` differ, as they can behave differently
+    pub(crate) fn is_identical(&self, other: &StackItem) -> bool {
+        match (self, other) {
+            (StackItem::Number(a), StackItem::Number(b)) => a.to_bits() == b.to_bits(),
+            (StackItem::Array(a), StackItem::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.is_identical(b))
+            }
+            _ => false,
+        }
+    }
+
     pub fn is_truthy(&self) -> bool {
         match self {
             Self::Number(k) => *k != 0.0,
@@ -25,13 +39,21 @@ impl StackItem {
         self,
         operator: &T,
     ) -> StackItem {
+        let Ok(item) = self.try_apply_unary_operator(&|a| Ok::<V, Infallible>(operator(a)));
+        item
+    }
+
+    pub(crate) fn try_apply_unary_operator<V: Into<StackItem>, E, T: Fn(f64) -> Result<V, E>>(
+        self,
+        operator: &T,
+    ) -> Result<StackItem, E> {
         match self {
-            StackItem::Number(a) => operator(a).into(),
-            StackItem::Array(arr) => StackItem::Array(
+            StackItem::Number(a) => operator(a).map(|k| k.into()),
+            StackItem::Array(arr) => Ok(StackItem::Array(
                 arr.into_iter()
-                    .map(|k| k.apply_unary_operator(operator))
-                    .collect(),
-            ),
+                    .map(|k| k.try_apply_unary_operator(operator))
+                    .collect::<Result<_, _>>()?,
+            )),
         }
     }
 
@@ -40,18 +62,28 @@ impl StackItem {
         other: StackItem,
         operator: &T,
     ) -> StackItem {
-        match (self, other) {
-            (StackItem::Number(a), StackItem::Number(b)) => StackItem::Number(operator(a, b)),
+        let Ok(item) =
+            self.try_apply_binary_operator(other, &|a, b| Ok::<f64, Infallible>(operator(a, b)));
+        item
+    }
+
+    fn try_apply_binary_operator<E, T: Fn(f64, f64) -> Result<f64, E>>(
+        self,
+        other: StackItem,
+        operator: &T,
+    ) -> Result<StackItem, E> {
+        Ok(match (self, other) {
+            (StackItem::Number(a), StackItem::Number(b)) => StackItem::Number(operator(a, b)?),
             (StackItem::Array(arr), StackItem::Number(b)) => StackItem::Array(
                 arr.into_iter()
-                    .map(|a| a.apply_binary_operator(StackItem::Number(b), operator))
-                    .collect(),
+                    .map(|a| a.try_apply_binary_operator(StackItem::Number(b), operator))
+                    .collect::<Result<_, _>>()?,
             ),
 
             (StackItem::Number(a), StackItem::Array(arr)) => StackItem::Array(
                 arr.into_iter()
-                    .map(|b| StackItem::Number(a).apply_binary_operator(b, operator))
-                    .collect(),
+                    .map(|b| StackItem::Number(a).try_apply_binary_operator(b, operator))
+                    .collect::<Result<_, _>>()?,
             ),
             (StackItem::Array(arr_a), StackItem::Array(arr_b)) => StackItem::Array(
                 arr_a
@@ -63,11 +95,21 @@ impl StackItem {
                             EitherOrBoth::Left(a) => (a, StackItem::default()),
                             EitherOrBoth::Right(b) => (StackItem::default(), b),
                         };
-                        left.apply_binary_operator(right, operator)
+                        left.try_apply_binary_operator(right, operator)
                     })
-                    .collect(),
+                    .collect::<Result<_, _>>()?,
             ),
-        }
+        })
+    }
+
+    pub(crate) fn checked_div(self, rhs: StackItem) -> Result<StackItem, RuntimeError> {
+        self.try_apply_binary_operator(rhs, &|a, b| {
+            if b == 0.0 {
+                Err(RuntimeError::DivisionByZero)
+            } else {
+                Ok(a / b)
+            }
+        })
     }
 }
 
@@ -0,0 +1,637 @@
+//! Places code on the tiling automatically. Code is written along straight lines, and when a line
+//! runs into code that is already placed it takes a detour through free tiles, using `<` and `>`
+//! to turn. Paths between existing code, like the way back to the start of a loop, are found the
+//! same way, and can cross each other on empty tiles.
+//!
+//! [`lay_out`] places a whole program written as a single line, with loops given separately.
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::ops::Range;
+
+use strum::VariantArray;
+
+use crate::analysis::cfg::StaticMode;
+use crate::geometry::tile_coordinate::TileCoordinate;
+use crate::geometry::tiling::Tiling;
+use crate::language::instructions::Instruction;
+use crate::language::{literals, source_map, FollowableDirection, LanguageState};
+
+#[derive(Clone, Debug)]
+pub struct LayoutOptions {
+    /// How many states a search for a detour or a path can visit before giving up
+    pub max_search: usize,
+    /// How many tiles going straight a turn is worth. Higher costs keep lines straight and use
+    /// fewer turn instructions, but paths take longer ways around.
+    pub turn_cost: usize,
+    /// How much placing code costs for every level it adds to the supertile around the origin that
+    /// holds all the code. Higher costs keep the code in smaller supertiles, so the coordinates in
+    /// the prefixes of its lines are shorter, but lines turn more often and take more tiles.
+    pub locality: usize,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        LayoutOptions {
+            max_search: 400_000,
+            turn_cost: 8,
+            locality: 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LayoutError {
+    /// No path through free tiles was found within [`LayoutOptions::max_search`] states
+    NoRoute,
+    /// A loop given to [`lay_out`] that is empty or goes past the end of the code
+    InvalidLoop { start: usize, end: usize },
+    /// The code given to [`lay_out`] has a character outside of a literal that turns the
+    /// instruction pointer, which would leave the line
+    Turn { position: usize, character: char },
+}
+
+impl std::fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayoutError::NoRoute => write!(f, "no path through free tiles was found"),
+            LayoutError::InvalidLoop { start, end } => {
+                write!(f, "the loop {start}..{end} is empty or goes past the end")
+            }
+            LayoutError::Turn {
+                position,
+                character,
+            } => write!(
+                f,
+                "the `{character}` at {position} turns the instruction pointer off the line"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+/// The instruction pointer arriving at `tile` going in `direction`
+pub struct Cursor<T: Tiling> {
+    pub tile: TileCoordinate<T>,
+    pub direction: T::Edge,
+}
+
+impl<T: Tiling> Clone for Cursor<T> {
+    fn clone(&self) -> Self {
+        Cursor {
+            tile: self.tile.clone(),
+            direction: self.direction,
+        }
+    }
+}
+
+impl<T: Tiling> PartialEq for Cursor<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.tile == other.tile && self.direction == other.direction
+    }
+}
+
+impl<T: Tiling> std::fmt::Debug for Cursor<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cursor")
+            .field("tile", &self.tile)
+            .field("direction", &self.direction)
+            .finish()
+    }
+}
+
+impl<T: Tiling> Cursor<T>
+where
+    T::Edge: FollowableDirection,
+{
+    /// The origin tile, going in the first direction of the tiling
+    pub fn origin() -> Self {
+        Cursor {
+            tile: TileCoordinate::new(vec![]).unwrap(),
+            direction: T::Edge::VARIANTS[0],
+        }
+    }
+
+    /// Where the instruction pointer arrives after leaving `tile` through `edge`
+    fn leave(tile: &TileCoordinate<T>, edge: T::Edge) -> Self {
+        let (tile, entry) = tile.go(edge).unwrap();
+        Cursor {
+            tile,
+            direction: entry.opposite(),
+        }
+    }
+}
+
+/// A side of a placed tile that has to be kept free, relative to the direction the instruction
+/// pointer leaves the tile in
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Side {
+    /// Where the instruction pointer goes when the tile turns it left
+    Left,
+    /// Where the instruction pointer goes when the tile turns it right
+    Right,
+    /// Where a second path has to come from to join the line when the tile is a `^` that turns
+    /// it left. The second path has to push a truthy value first, the line itself a falsy one.
+    MergeLeft,
+    /// The same for a `v` that turns the second path right
+    MergeRight,
+}
+
+/// The direction the instruction pointer has to arrive in so that turning sends it towards
+/// `direction`
+fn merge_direction<E: FollowableDirection + PartialEq + VariantArray>(
+    direction: E,
+    turn: impl Fn(E) -> E,
+) -> E {
+    *E::VARIANTS
+        .iter()
+        .find(|edge| turn(**edge) == direction)
+        .unwrap()
+}
+
+/// The edges aren't `Eq`, so visited states are keyed on their position in `VARIANTS`
+fn direction_index<E: PartialEq + VariantArray>(direction: E) -> usize {
+    E::VARIANTS
+        .iter()
+        .position(|edge| *edge == direction)
+        .unwrap()
+}
+
+/// How many tiles a path leaving or joining code goes straight through next to it. They are kept
+/// free until the path is placed, so other code can't wall the path in.
+const PORT_LENGTH: usize = 3;
+
+/// The tiles going straight on from a cursor, starting with its own
+fn ahead<T: Tiling>(cursor: &Cursor<T>) -> Vec<TileCoordinate<T>>
+where
+    T::Edge: FollowableDirection,
+{
+    let mut cursor = cursor.clone();
+    let mut tiles = vec![cursor.tile.clone()];
+    while tiles.len() < PORT_LENGTH {
+        cursor = Cursor::leave(&cursor.tile, cursor.direction);
+        tiles.push(cursor.tile.clone());
+    }
+    tiles
+}
+
+/// The tiles a path arriving at `target` goes straight through last, starting with the one next
+/// to the target
+fn approach<T: Tiling>(target: &Cursor<T>) -> Vec<TileCoordinate<T>>
+where
+    T::Edge: FollowableDirection,
+{
+    ahead(&Cursor::leave(&target.tile, target.direction.opposite()))
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Action {
+    Straight,
+    Left,
+    Right,
+}
+
+/// A state visited while searching, with the action that led to it
+struct Node<T: Tiling> {
+    cursor: Cursor<T>,
+    parent: Option<(usize, Action)>,
+}
+
+pub struct Layout<T: Tiling> {
+    code: HashMap<TileCoordinate<T>, char>,
+    /// Tiles that have to stay empty, because a path crosses them or code still has to go there
+    reserved: HashSet<TileCoordinate<T>>,
+    /// The reserved tiles next to the sides of placed code, by the tile next to the code. They
+    /// are freed again for the path using the side.
+    ports: HashMap<TileCoordinate<T>, Vec<TileCoordinate<T>>>,
+    /// Tiles a single path goes straight through, with its direction there. Another path can
+    /// cross them through the other pair of edges, as the tiles stay empty.
+    crossings: HashMap<TileCoordinate<T>, T::Edge>,
+    /// The length of the longest coordinate of the code, the level of the supertile around the
+    /// origin that holds all of it
+    extent: usize,
+    entry: Cursor<T>,
+    options: LayoutOptions,
+}
+
+impl<T: Tiling> Layout<T>
+where
+    T::Edge: FollowableDirection,
+{
+    /// Starts a layout for a program entered at `entry`, returns the cursor for the first line
+    pub fn new(entry: Cursor<T>, options: LayoutOptions) -> (Self, Cursor<T>) {
+        let layout = Layout {
+            code: HashMap::new(),
+            reserved: HashSet::from([entry.tile.clone()]),
+            ports: HashMap::new(),
+            crossings: HashMap::new(),
+            extent: 0,
+            entry: entry.clone(),
+            options,
+        };
+        (layout, entry)
+    }
+
+    fn is_free(&self, tile: &TileCoordinate<T>) -> bool {
+        !self.code.contains_key(tile) && !self.reserved.contains(tile)
+    }
+
+    /// Whether a path going in the cursor's direction can cross another path on its tile
+    fn can_cross(&self, cursor: &Cursor<T>) -> bool {
+        self.crossings.get(&cursor.tile).is_some_and(|direction| {
+            *direction != cursor.direction && *direction != cursor.direction.opposite()
+        })
+    }
+
+    /// The cost of placing code on a tile for how much it grows the supertile holding the code
+    fn locality_cost(&self, tile: &TileCoordinate<T>) -> usize {
+        self.options.locality * tile.len().saturating_sub(self.extent)
+    }
+
+    fn insert_code(&mut self, tile: TileCoordinate<T>, character: char) {
+        self.extent = self.extent.max(tile.len());
+        self.code.insert(tile, character);
+    }
+
+    /// The cost of placing a turn on a tile
+    fn turn_cost(&self, tile: &TileCoordinate<T>) -> usize {
+        self.options.turn_cost + self.locality_cost(tile)
+    }
+
+    /// The tiles of the path leading to a node, including the node itself
+    fn path_tiles(nodes: &[Node<T>], mut index: usize) -> impl Iterator<Item = &TileCoordinate<T>> {
+        let mut done = false;
+        std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            let node = &nodes[index];
+            match node.parent {
+                Some((parent, _)) => index = parent,
+                None => done = true,
+            }
+            Some(&node.cursor.tile)
+        })
+    }
+
+    /// Searches the states reachable from `start` over free tiles, cheapest paths first, for one
+    /// that is accepted. `can_enter` allows stepping on tiles that aren't free.
+    fn search(
+        &self,
+        start: &Cursor<T>,
+        can_enter: impl Fn(&TileCoordinate<T>) -> bool,
+        accept: impl Fn(&[Node<T>], usize) -> bool,
+    ) -> Result<(Vec<Node<T>>, usize), LayoutError> {
+        let mut nodes = vec![Node {
+            cursor: start.clone(),
+            parent: None,
+        }];
+        // Accepted nodes go back in the queue with the cost of placing code there, and are only
+        // taken when they come out again
+        let mut queue = BinaryHeap::from([Reverse((0, 0, false))]);
+        let mut visited = HashSet::new();
+
+        while let Some(Reverse((cost, index, accepted))) = queue.pop() {
+            if accepted {
+                return Ok((nodes, index));
+            }
+            let cursor = nodes[index].cursor.clone();
+            if !visited.insert((cursor.tile.clone(), direction_index(cursor.direction))) {
+                continue;
+            }
+            if visited.len() > self.options.max_search {
+                break;
+            }
+            // Nothing can be placed where paths cross, and they have to go straight on
+            let crossing = index > 0 && self.crossings.contains_key(&cursor.tile);
+            // The line goes on after the code placed here, so the tile after it counts as well
+            if !crossing && accept(&nodes, index) {
+                let next = Cursor::leave(&cursor.tile, cursor.direction);
+                let placing_cost =
+                    self.locality_cost(&cursor.tile) + self.locality_cost(&next.tile);
+                queue.push(Reverse((cost + placing_cost, index, true)));
+            }
+            // Paths can end on code, but never go through it
+            if index > 0 && self.code.contains_key(&cursor.tile) {
+                continue;
+            }
+
+            let actions: &[Action] = if crossing {
+                &[Action::Straight]
+            } else {
+                &[Action::Straight, Action::Left, Action::Right]
+            };
+            for &action in actions {
+                let (direction, step_cost) = match action {
+                    Action::Straight => (cursor.direction, 1),
+                    Action::Left => (cursor.direction.turn_left(), self.turn_cost(&cursor.tile)),
+                    Action::Right => (cursor.direction.turn_right(), self.turn_cost(&cursor.tile)),
+                };
+                let next = Cursor::leave(&cursor.tile, direction);
+                if !(self.is_free(&next.tile) || can_enter(&next.tile) || self.can_cross(&next))
+                    || Self::path_tiles(&nodes, index).any(|tile| *tile == next.tile)
+                {
+                    continue;
+                }
+                queue.push(Reverse((cost + step_cost, nodes.len(), false)));
+                nodes.push(Node {
+                    cursor: next,
+                    parent: Some((index, action)),
+                });
+            }
+        }
+        Err(LayoutError::NoRoute)
+    }
+
+    /// Writes the turns of the path leading to a node, the tiles it goes straight through stay
+    /// empty
+    fn commit_path(&mut self, nodes: &[Node<T>], mut index: usize) {
+        while let Some((parent, action)) = nodes[index].parent {
+            let Cursor { tile, direction } = nodes[parent].cursor.clone();
+            self.reserved.remove(&tile);
+            match action {
+                // A tile crossed by a second path can't be crossed again
+                Action::Straight if self.crossings.remove(&tile).is_some() => {
+                    self.reserved.insert(tile);
+                }
+                Action::Straight => {
+                    self.reserved.insert(tile.clone());
+                    self.crossings.insert(tile, direction);
+                }
+                Action::Left => {
+                    self.insert_code(tile, '<');
+                }
+                Action::Right => {
+                    self.insert_code(tile, '>');
+                }
+            }
+            index = parent;
+        }
+    }
+
+    /// Frees the tiles kept free for a path leaving or joining code next to `tile`
+    fn open_port(&mut self, tile: &TileCoordinate<T>) -> Vec<TileCoordinate<T>> {
+        let port = self.ports.remove(tile).unwrap_or_default();
+        for tile in &port {
+            self.reserved.remove(tile);
+        }
+        port
+    }
+
+    /// Keeps the tiles of a port free again, after the path using it couldn't be placed
+    fn close_port(&mut self, port: Vec<TileCoordinate<T>>) {
+        if let Some(first) = port.first() {
+            self.reserved.extend(port.iter().cloned());
+            self.ports.insert(first.clone(), port);
+        }
+    }
+
+    /// Places a character at the cursor, or after a detour if the line can't continue from
+    /// there, and moves the cursor past it. Returns a cursor for every side, where a path
+    /// leaving the tile on that side arrives. For [`Side::MergeLeft`] and [`Side::MergeRight`] it
+    /// is where the second path has to arrive to join, see [`Layout::route`].
+    pub fn place(
+        &mut self,
+        cursor: &mut Cursor<T>,
+        character: char,
+        sides: &[Side],
+    ) -> Result<Vec<Cursor<T>>, LayoutError> {
+        let exits = |cursor: &Cursor<T>| {
+            sides
+                .iter()
+                .map(|side| match side {
+                    Side::Left => Cursor::leave(&cursor.tile, cursor.direction.turn_left()),
+                    Side::Right => Cursor::leave(&cursor.tile, cursor.direction.turn_right()),
+                    Side::MergeLeft => Cursor {
+                        tile: cursor.tile.clone(),
+                        direction: merge_direction(cursor.direction, T::Edge::turn_left),
+                    },
+                    Side::MergeRight => Cursor {
+                        tile: cursor.tile.clone(),
+                        direction: merge_direction(cursor.direction, T::Edge::turn_right),
+                    },
+                })
+                .collect::<Vec<_>>()
+        };
+        // The tiles kept free for the line and the paths on the sides
+        let kept_free = |cursor: &Cursor<T>| {
+            let mut tiles = vec![Cursor::leave(&cursor.tile, cursor.direction).tile];
+            for (side, exit) in sides.iter().zip(exits(cursor)) {
+                tiles.extend(match side {
+                    Side::Left | Side::Right => ahead(&exit),
+                    Side::MergeLeft | Side::MergeRight => approach(&exit),
+                });
+            }
+            tiles
+        };
+
+        let port = self.open_port(&cursor.tile);
+        let result = self.search(
+            cursor,
+            |_| false,
+            |nodes, index| {
+                let tiles = kept_free(&nodes[index].cursor);
+                tiles.iter().enumerate().all(|(i, tile)| {
+                    self.is_free(tile)
+                        && !tiles[..i].contains(tile)
+                        && !Self::path_tiles(nodes, index).any(|path_tile| path_tile == tile)
+                })
+            },
+        );
+        let (nodes, index) = match result {
+            Ok(found) => found,
+            Err(error) => {
+                self.close_port(port);
+                return Err(error);
+            }
+        };
+        self.commit_path(&nodes, index);
+
+        let placed = nodes[index].cursor.clone();
+        self.reserved.remove(&placed.tile);
+        self.insert_code(placed.tile.clone(), character);
+
+        let next = Cursor::leave(&placed.tile, placed.direction);
+        self.reserved.insert(next.tile.clone());
+        let exits = exits(&placed);
+        for (side, exit) in sides.iter().zip(&exits) {
+            let port = match side {
+                Side::Left | Side::Right => ahead(exit),
+                Side::MergeLeft | Side::MergeRight => approach(exit),
+            };
+            self.close_port(port);
+        }
+        *cursor = next;
+        Ok(exits)
+    }
+
+    /// Places every character of `code` in a line
+    pub fn place_str(&mut self, cursor: &mut Cursor<T>, code: &str) -> Result<(), LayoutError> {
+        code.chars()
+            .try_for_each(|character| self.place(cursor, character, &[]).map(|_| ()))
+    }
+
+    /// Connects a cursor to a target, like the cursor [`Layout::place`] returns for a merge,
+    /// with a path through free tiles
+    pub fn route(&mut self, from: &Cursor<T>, to: &Cursor<T>) -> Result<(), LayoutError> {
+        let departure = self.open_port(&from.tile);
+        let arrival = self.open_port(&approach(to)[0]);
+        let result = self.search(
+            from,
+            |tile| *tile == to.tile,
+            |nodes, index| nodes[index].cursor == *to,
+        );
+        match result {
+            Ok((nodes, index)) => {
+                self.commit_path(&nodes, index);
+                Ok(())
+            }
+            Err(error) => {
+                self.close_port(departure);
+                self.close_port(arrival);
+                Err(error)
+            }
+        }
+    }
+
+    pub fn into_program(self) -> LanguageState<T> {
+        let code = self
+            .code
+            .into_iter()
+            .map(|(tile, character)| (tile, (character, Instruction::from_char(character))))
+            .collect();
+        LanguageState::new(
+            code,
+            source_map::SourceMap::default(),
+            (self.entry.tile, self.entry.direction),
+        )
+    }
+}
+
+/// Finds the first character that turns the instruction pointer when the code runs as a straight
+/// line, skipping strings, characters and numbers like the interpreter does
+fn find_turn(characters: &[char]) -> Option<usize> {
+    let mut mode = StaticMode::Normal;
+    for (position, &character) in characters.iter().enumerate() {
+        let instruction = Instruction::from_char(character);
+        mode = match mode {
+            StaticMode::Number { has_point }
+                if literals::is_number_literal_character(
+                    if has_point { "." } else { "" },
+                    character,
+                ) =>
+            {
+                StaticMode::Number {
+                    has_point: has_point || character == '.',
+                }
+            }
+            StaticMode::Normal | StaticMode::Number { .. } => match instruction {
+                Some(
+                    Instruction::TurnLeft
+                    | Instruction::TurnRight
+                    | Instruction::TurnLeftIf
+                    | Instruction::TurnRightIf
+                    | Instruction::TurnAround,
+                ) => return Some(position),
+                Some(Instruction::StartCharacterString) => StaticMode::CharString,
+                Some(Instruction::StartArrayString) => StaticMode::ArrayString,
+                Some(Instruction::StartCharacter) => StaticMode::Char,
+                Some(Instruction::StartNumber) => StaticMode::Number { has_point: false },
+                _ => StaticMode::Normal,
+            },
+            StaticMode::Escape(inner, mut sequence) => {
+                sequence.push(character);
+                match literals::resolve_escape(&sequence) {
+                    Ok(None) => StaticMode::Escape(inner, sequence),
+                    Ok(Some(_)) if *inner != StaticMode::Char => *inner,
+                    // An invalid escape stops the program anyway
+                    _ => StaticMode::Normal,
+                }
+            }
+            mode @ (StaticMode::Char | StaticMode::CharString | StaticMode::ArrayString)
+                if character == '\\' =>
+            {
+                StaticMode::Escape(Box::new(mode), String::new())
+            }
+            StaticMode::Char => StaticMode::Normal,
+            StaticMode::ArrayString if instruction == Some(Instruction::StartArrayString) => {
+                StaticMode::Normal
+            }
+            StaticMode::CharString if instruction == Some(Instruction::StartCharacterString) => {
+                StaticMode::Normal
+            }
+            mode => mode,
+        };
+    }
+    None
+}
+
+/// Places a program written as a single line, starting at the origin like a parsed program.
+/// `loops` are ranges of characters of `code` that repeat: after the last character of a loop the
+/// top of the stack is popped, and the loop starts again if it is truthy. Loops can be nested or
+/// overlap, but shouldn't start or end inside a string or number, as they add code there. The code
+/// can't turn the instruction pointer itself, other than with `loops`.
+pub fn lay_out<T: Tiling>(
+    code: &str,
+    loops: &[Range<usize>],
+    options: &LayoutOptions,
+) -> Result<LanguageState<T>, LayoutError>
+where
+    T::Edge: FollowableDirection,
+{
+    let characters: Vec<char> = code.chars().collect();
+    if let Some(invalid) = loops
+        .iter()
+        .find(|range| range.is_empty() || range.end > characters.len())
+    {
+        return Err(LayoutError::InvalidLoop {
+            start: invalid.start,
+            end: invalid.end,
+        });
+    }
+    if let Some(position) = find_turn(&characters) {
+        return Err(LayoutError::Turn {
+            position,
+            character: characters[position],
+        });
+    }
+
+    let (mut layout, mut cursor) = Layout::new(Cursor::origin(), options.clone());
+    let mut starts = vec![None; loops.len()];
+    let mut ends = vec![];
+    for position in 0..=characters.len() {
+        // The loops ending here leave with `:v` and drop the falsy copy, the inner ones first
+        let mut ending: Vec<_> = (0..loops.len())
+            .filter(|index| loops[*index].end == position)
+            .collect();
+        ending.sort_by_key(|index| Reverse(loops[*index].start));
+        for index in ending {
+            layout.place(&mut cursor, ':', &[])?;
+            let exits = layout.place(&mut cursor, 'v', &[Side::Right])?;
+            layout.place(&mut cursor, '~', &[])?;
+            ends.push((index, exits[0].clone()));
+        }
+
+        // The loops starting here join the line at a `0v`, the outer ones first
+        let mut starting: Vec<_> = (0..loops.len())
+            .filter(|index| loops[*index].start == position)
+            .collect();
+        starting.sort_by_key(|index| Reverse(loops[*index].end));
+        for index in starting {
+            layout.place(&mut cursor, '0', &[])?;
+            let exits = layout.place(&mut cursor, 'v', &[Side::MergeRight])?;
+            starts[index] = Some(exits[0].clone());
+        }
+
+        if let Some(character) = characters.get(position) {
+            layout.place(&mut cursor, *character, &[])?;
+        }
+    }
+
+    // Shorter loops are placed first, so their paths stay close to the line
+    ends.sort_by_key(|(index, _)| loops[*index].len());
+    for (index, from) in ends {
+        layout.route(&from, starts[index].as_ref().unwrap())?;
+    }
+    Ok(layout.into_program())
+}
@@ -1,2 +1,7 @@
+pub mod analysis;
+pub mod bytecode;
 pub mod geometry;
 pub mod language;
+pub mod layout;
+pub mod minimize;
+pub mod translate;
@@ -0,0 +1,234 @@
+//! Shrinks programs to the code that can actually run
+//!
+//! Tiles the instruction pointer can never reach are dropped, and so are characters without an
+//! instruction that are only ever stepped on outside of strings and character literals, where
+//! they do the same as an empty tile. The rest is written out again by
+//! [`LanguageState::serialize`], which starts every line at the shortest coordinate it can.
+//! Dropped characters next to the remaining code stay where they join two lines, as they either
+//! never run or do nothing.
+//!
+//! The static analysis can't prove the result does the same, so [`minimize`] runs both programs
+//! on the given inputs and only returns the result if they agree.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use strum::VariantArray;
+
+use crate::analysis::cfg::{AnalysisOptions, ControlFlowGraph, StaticMode};
+use crate::geometry::tile_coordinate::TileCoordinate;
+use crate::geometry::tiling::Tiling;
+use crate::language::instructions::Instruction;
+use crate::language::loop_detection::LoopDetection;
+use crate::language::{literals, FollowableDirection, LanguageState, RunOutcome};
+
+#[derive(Clone, Debug)]
+pub struct MinimizeOptions {
+    /// Paths that cross more empty tiles in a row than this are considered to have left the
+    /// program, so the code after such a gap is dropped. Loop detection stops both programs in
+    /// the same places when checking the result.
+    pub analysis: AnalysisOptions,
+    /// How many steps each program gets per input when checking the result, programs that
+    /// haven't stopped by then are only compared as far as they got
+    pub max_steps: usize,
+}
+
+impl Default for MinimizeOptions {
+    fn default() -> Self {
+        MinimizeOptions {
+            analysis: AnalysisOptions {
+                max_empty_steps: 1000,
+            },
+            max_steps: 100_000,
+        }
+    }
+}
+
+/// The minimized program did something different from the original on one of the inputs
+#[derive(Clone, PartialEq, Debug)]
+pub struct Mismatch {
+    /// The index of the input in the list given to [`minimize`]
+    pub input: usize,
+    /// How the programs stopped, `None` if one didn't within the steps it got
+    pub original: Option<RunOutcome>,
+    pub minimized: Option<RunOutcome>,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let outcome = |outcome: &Option<RunOutcome>| match outcome {
+            Some(outcome) => format!("{outcome:?}"),
+            None => "still running".to_string(),
+        };
+
+        write!(
+            f,
+            "the minimized program behaves differently on input {}",
+            self.input
+        )?;
+        if self.original != self.minimized {
+            write!(
+                f,
+                ", the original ends with {} but the minimized program with {}",
+                outcome(&self.original),
+                outcome(&self.minimized)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether a character without an instruction does the same as an empty tile in this mode. Only
+/// normal mode and the end of a number literal ignore it, everywhere else it is part of a literal.
+fn is_ignored(character: char, mode: &StaticMode) -> bool {
+    match mode {
+        StaticMode::Normal => true,
+        StaticMode::Number { has_point } => {
+            !literals::is_number_literal_character(if *has_point { "." } else { "" }, character)
+        }
+        _ => false,
+    }
+}
+
+fn build<T: Tiling>(
+    original: &LanguageState<T>,
+    tiles: &HashSet<&TileCoordinate<T>>,
+) -> LanguageState<T>
+where
+    T::Edge: FollowableDirection,
+{
+    let code: HashMap<_, _> = tiles
+        .iter()
+        .filter_map(|&tile| {
+            let character = original.get_char(tile)?;
+            Some((tile.clone(), (character, Instruction::from_char(character))))
+        })
+        .collect();
+
+    let mut program = LanguageState::new(
+        code,
+        Default::default(),
+        (original.get_entry().0.clone(), original.get_entry().1),
+    );
+    program.set_number_format(original.get_number_format().clone());
+    program
+}
+
+/// How long the source of a program is, fewer lines first and then fewer characters
+fn size<T: Tiling>(program: &LanguageState<T>) -> (usize, usize)
+where
+    T::Tile: Into<char>,
+    T::Edge: FollowableDirection + Display,
+{
+    let source = program.serialize();
+    (source.lines().count(), source.len())
+}
+
+/// Runs a program until it stops or runs out of steps, returning what it wrote and how it stopped.
+/// It isn't compiled, so loop detection counts every empty tile like the analysis does.
+fn run<T: Tiling>(
+    mut program: LanguageState<T>,
+    input: &[u8],
+    options: &MinimizeOptions,
+) -> (Vec<u8>, Option<RunOutcome>)
+where
+    T::Edge: FollowableDirection,
+{
+    let detection = LoopDetection {
+        max_empty_steps: options.analysis.max_empty_steps,
+    };
+    let output = program.run_detecting_loops(input, detection, options.max_steps);
+    (output, program.get_outcome())
+}
+
+/// Whether two runs agree. Loops can get shorter when characters are dropped, so any two
+/// infinite loops are the same. A program that is still running may just be slower, so then its
+/// output only has to be the start of the other one.
+fn agree(
+    (output, outcome): &(Vec<u8>, Option<RunOutcome>),
+    (other_output, other_outcome): &(Vec<u8>, Option<RunOutcome>),
+) -> bool {
+    match (outcome, other_outcome) {
+        (Some(RunOutcome::InfiniteLoop { .. }), Some(RunOutcome::InfiniteLoop { .. })) => {
+            output == other_output
+        }
+        (Some(_), Some(_)) => output == other_output && outcome == other_outcome,
+        (Some(_), None) => output.starts_with(other_output),
+        (None, Some(_)) => other_output.starts_with(output),
+        (None, None) => output.starts_with(other_output) || other_output.starts_with(output),
+    }
+}
+
+/// Drops the code that never runs and rewrites the program with as few lines as it can, then
+/// checks it still does the same on every input
+pub fn minimize<T: Tiling>(
+    program: &LanguageState<T>,
+    inputs: &[&[u8]],
+    options: &MinimizeOptions,
+) -> Result<LanguageState<T>, Mismatch>
+where
+    T::Tile: Into<char>,
+    T::Edge: FollowableDirection + Display,
+{
+    let graph = ControlFlowGraph::build(program, &options.analysis);
+
+    // A tile can go if the instruction pointer ignores it in every mode it gets there in
+    let mut modes: HashMap<&TileCoordinate<T>, Vec<&StaticMode>> = HashMap::new();
+    for state in graph.blocks.iter().flat_map(|block| &block.states) {
+        modes.entry(&state.tile).or_default().push(&state.mode);
+    }
+    let mut kept = HashSet::new();
+    for (tile, modes) in modes {
+        if let Some(character) = program.get_char(tile) {
+            if Instruction::from_char(character).is_some()
+                || !modes.iter().all(|mode| is_ignored(character, mode))
+            {
+                kept.insert(tile);
+            }
+        }
+    }
+
+    // Dropped characters next to the code can fill the gap between two lines, so they start out
+    // in place and only go if that doesn't make the source longer
+    let mut fillers: Vec<_> = program
+        .get_code_tiles()
+        .filter(|tile| {
+            !kept.contains(tile)
+                && T::Edge::VARIANTS.iter().any(|&direction| {
+                    tile.go(direction)
+                        .is_ok_and(|(next, _)| kept.contains(&next))
+                })
+        })
+        .collect();
+    fillers.sort_by_cached_key(|tile| {
+        let name = tile.to_string();
+        (name.len(), name)
+    });
+    kept.extend(fillers.iter().copied());
+    let mut minimized = build(program, &kept);
+    let mut best = size(&minimized);
+    for tile in fillers {
+        kept.remove(tile);
+        let candidate = build(program, &kept);
+        let candidate_size = size(&candidate);
+        if candidate_size <= best {
+            (minimized, best) = (candidate, candidate_size);
+        } else {
+            kept.insert(tile);
+        }
+    }
+
+    for (index, input) in inputs.iter().enumerate() {
+        let original = run(program.clone(), input, options);
+        let result = run(minimized.clone(), input, options);
+        if !agree(&original, &result) {
+            return Err(Mismatch {
+                input: index,
+                original: original.1,
+                minimized: result.1,
+            });
+        }
+    }
+
+    Ok(minimized)
+}
@@ -0,0 +1,459 @@
+//! Compiles Befunge-93 to Painrose
+//!
+//! Every state the Befunge program can be in, a cell with a direction and whether a string is
+//! being read, is followed to find the code it runs and where it goes next. The states are then
+//! grouped into blocks that run straight through, and the blocks are placed one after the other
+//! along a single line:
+//!
+//! ```text
+//! 0v0v code :v~ 1> 0v code ; ...
+//!   | |      |   |  |
+//! ```
+//!
+//! A block starts with a `0v` for every path joining it, each path pushes a 1 before it leaves
+//! the line, so the `v` turns it onto the line while the line itself goes straight on. `_` and `|`
+//! leave the line with `:v` when the value is not 0, the block for 0 follows on the line or is
+//! jumped to with `1>`. The paths are placed last and cross each other where they have to.
+//!
+//! Both engines keep numbers as floats, so `/` and `%` are rounded towards zero with some extra
+//! code, like in C. Dividing by zero is a runtime error rather than asking for the result. `p`,
+//! `g` and `?` have no equivalent, the cells with them that the program can reach are reported by
+//! [`BefungeError::Untranslatable`].
+use std::collections::{HashMap, VecDeque};
+
+use crate::geometry::tiling::Tiling;
+use crate::language::{FollowableDirection, LanguageState};
+use crate::layout::{Cursor, Layout, LayoutError, LayoutOptions, Side};
+
+#[derive(Clone, Debug, Default)]
+pub struct BefungeOptions {
+    pub layout: LayoutOptions,
+}
+
+/// A cell the program can reach that has no Painrose equivalent
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Untranslatable {
+    /// Both starting at 0
+    pub line: usize,
+    pub column: usize,
+    pub character: char,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum BefungeError {
+    /// Every reachable cell that can't be translated, in reading order
+    Untranslatable(Vec<Untranslatable>),
+    Layout(LayoutError),
+}
+
+impl std::fmt::Display for BefungeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BefungeError::Untranslatable(cells) => {
+                write!(f, "can't translate")?;
+                for (index, cell) in cells.iter().enumerate() {
+                    let separator = if index == 0 { "" } else { "," };
+                    write!(
+                        f,
+                        "{separator} `{}` at line {}, column {}",
+                        cell.character,
+                        cell.line + 1,
+                        cell.column + 1
+                    )?;
+                }
+                Ok(())
+            }
+            BefungeError::Layout(error) => write!(f, "could not lay out the program: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for BefungeError {}
+
+impl From<LayoutError> for BefungeError {
+    fn from(error: LayoutError) -> Self {
+        BefungeError::Layout(error)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+enum Direction {
+    Right,
+    Down,
+    Left,
+    Up,
+}
+
+/// Where the Befunge program is and what it is doing
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+struct State {
+    column: usize,
+    line: usize,
+    direction: Direction,
+    string: bool,
+}
+
+/// Where a state goes after running its code
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Next<S> {
+    Go(S),
+    /// Pops a value and goes to `zero` if it is 0
+    Branch {
+        zero: S,
+        other: S,
+    },
+    Stop,
+}
+
+impl<S: Copy> Next<S> {
+    fn targets(&self) -> Vec<S> {
+        match *self {
+            Next::Go(target) => vec![target],
+            Next::Branch { zero, other } => vec![zero, other],
+            Next::Stop => vec![],
+        }
+    }
+
+    fn map<U>(self, mut f: impl FnMut(S) -> U) -> Next<U> {
+        match self {
+            Next::Go(target) => Next::Go(f(target)),
+            Next::Branch { zero, other } => Next::Branch {
+                zero: f(zero),
+                other: f(other),
+            },
+            Next::Stop => Next::Stop,
+        }
+    }
+}
+
+/// The playfield, at least 80 by 25 cells like in Befunge-93
+struct Grid {
+    cells: Vec<Vec<char>>,
+    width: usize,
+    height: usize,
+}
+
+impl Grid {
+    fn new(source: &str) -> Self {
+        let cells: Vec<Vec<char>> = source
+            .lines()
+            .map(|line| line.trim_end_matches('\r').chars().collect())
+            .collect();
+        let width = cells.iter().map(Vec::len).max().unwrap_or(0).max(80);
+        let height = cells.len().max(25);
+        Grid {
+            cells,
+            width,
+            height,
+        }
+    }
+
+    fn get(&self, state: &State) -> char {
+        self.cells
+            .get(state.line)
+            .and_then(|line| line.get(state.column))
+            .copied()
+            .unwrap_or(' ')
+    }
+
+    /// Moves one cell on, wrapping around at the edges
+    fn advance(&self, mut state: State) -> State {
+        match state.direction {
+            Direction::Right => state.column = (state.column + 1) % self.width,
+            Direction::Down => state.line = (state.line + 1) % self.height,
+            Direction::Left => state.column = (state.column + self.width - 1) % self.width,
+            Direction::Up => state.line = (state.line + self.height - 1) % self.height,
+        }
+        state
+    }
+
+    fn turn(&self, state: State, direction: Direction) -> State {
+        self.advance(State { direction, ..state })
+    }
+}
+
+/// Rounds the number on top to the nearest integer, using that adding 1.5 * 2^52 leaves no bits
+/// for a fraction
+const ROUND: &str = "$6755399441055744+$6755399441055744_+";
+
+/// Code turning `a b` into `a b m c ab t`, where `t` is `a / b` rounded towards zero. The rounded
+/// quotient `m` is one too far from zero when `c` is 1, because the remainder it leaves has a
+/// different sign than `a`, so `t` moves it back by `c` times the sign of `ab`.
+fn divide() -> String {
+    format!(":2c/{ROUND}1c1c*3c-3c*0(3c3c*:0(1c0)-2c*3c-")
+}
+
+/// Keeps only the top of the 6 items [`divide`] leaves, by indexing an array of them
+const DIVIDE_END: &str = "6a5[";
+
+/// Code pushing a character
+fn character(value: char) -> String {
+    if value.is_ascii_graphic() && value != '\\' {
+        format!("`{value}")
+    } else {
+        format!("`\\u{{{:x}}}", value as u32)
+    }
+}
+
+/// The code a state runs and where it goes next
+fn step(grid: &Grid, state: State) -> Result<(String, Next<State>), Untranslatable> {
+    let value = grid.get(&state);
+    if state.string {
+        return Ok(match value {
+            '"' => (
+                String::new(),
+                Next::Go(grid.advance(State {
+                    string: false,
+                    ..state
+                })),
+            ),
+            _ => (character(value), Next::Go(grid.advance(state))),
+        });
+    }
+
+    let code = match value {
+        '0'..='9' => value.to_string(),
+        '+' | '*' | ':' => value.to_string(),
+        '-' => "_+".to_string(),
+        '/' => divide() + DIVIDE_END,
+        '%' => divide() + "4c*5c-" + DIVIDE_END,
+        '!' => "0=".to_string(),
+        '`' => ")".to_string(),
+        '\\' => "s".to_string(),
+        '$' => "~".to_string(),
+        '.' => "N".to_string(),
+        ',' => "I".to_string(),
+        '&' => "n".to_string(),
+        '~' => "i".to_string(),
+        ' ' | '>' | '<' | '^' | 'v' | '#' | '"' | '_' | '|' | '@' => String::new(),
+        _ => {
+            return Err(Untranslatable {
+                line: state.line,
+                column: state.column,
+                character: value,
+            })
+        }
+    };
+
+    let next = match value {
+        '>' => Next::Go(grid.turn(state, Direction::Right)),
+        'v' => Next::Go(grid.turn(state, Direction::Down)),
+        '<' => Next::Go(grid.turn(state, Direction::Left)),
+        '^' => Next::Go(grid.turn(state, Direction::Up)),
+        '#' => Next::Go(grid.advance(grid.advance(state))),
+        '"' => Next::Go(grid.advance(State {
+            string: true,
+            ..state
+        })),
+        '_' => Next::Branch {
+            zero: grid.turn(state, Direction::Right),
+            other: grid.turn(state, Direction::Left),
+        },
+        '|' => Next::Branch {
+            zero: grid.turn(state, Direction::Down),
+            other: grid.turn(state, Direction::Up),
+        },
+        '@' => Next::Stop,
+        _ => Next::Go(grid.advance(state)),
+    };
+    Ok((code, next))
+}
+
+/// Code that runs straight through, and where it goes next by block index
+struct Block {
+    code: String,
+    next: Next<usize>,
+}
+
+/// Follows every reachable state and groups them into blocks, the first block is the entry
+fn blocks(grid: &Grid) -> Result<Vec<Block>, BefungeError> {
+    let start = State {
+        column: 0,
+        line: 0,
+        direction: Direction::Right,
+        string: false,
+    };
+
+    let mut steps = HashMap::new();
+    let mut untranslatable = vec![];
+    let mut predecessors: HashMap<State, usize> = HashMap::new();
+    let mut leaders = vec![start];
+    let mut queue = VecDeque::from([start]);
+    while let Some(state) = queue.pop_front() {
+        if steps.contains_key(&state) {
+            continue;
+        }
+        let (code, next) = step(grid, state).unwrap_or_else(|cell| {
+            untranslatable.push(cell);
+            (String::new(), Next::Stop)
+        });
+        if let Next::Branch { zero, other } = next {
+            leaders.extend([zero, other]);
+        }
+        for target in next.targets() {
+            *predecessors.entry(target).or_default() += 1;
+            queue.push_back(target);
+        }
+        steps.insert(state, (code, next));
+    }
+
+    if !untranslatable.is_empty() {
+        untranslatable.sort_by_key(|cell| (cell.line, cell.column));
+        untranslatable.dedup();
+        return Err(BefungeError::Untranslatable(untranslatable));
+    }
+
+    // Blocks start where the program starts, where it branches to and where paths join
+    leaders.extend(
+        predecessors
+            .iter()
+            .filter(|(_, count)| **count > 1)
+            .map(|(state, _)| *state),
+    );
+    let mut indices = HashMap::new();
+    let mut order = vec![];
+    for leader in leaders {
+        indices.entry(leader).or_insert_with(|| {
+            order.push(leader);
+            order.len() - 1
+        });
+    }
+
+    Ok(order
+        .into_iter()
+        .map(|leader| {
+            let mut code = String::new();
+            let mut state = leader;
+            loop {
+                let (state_code, next) = &steps[&state];
+                code += state_code;
+                match *next {
+                    Next::Go(target) if !indices.contains_key(&target) => state = target,
+                    next => {
+                        return Block {
+                            code,
+                            next: next.map(|target| indices[&target]),
+                        }
+                    }
+                }
+            }
+        })
+        .collect())
+}
+
+/// The order to place the blocks in, so that as many blocks as possible follow the block that
+/// goes to them, or the block for 0 after a branch
+fn placement_order(blocks: &[Block]) -> Vec<usize> {
+    let mut placed = vec![false; blocks.len()];
+    let mut order = vec![];
+    let mut stack = vec![0];
+    while let Some(index) = stack.pop() {
+        if placed[index] {
+            continue;
+        }
+        placed[index] = true;
+        order.push(index);
+        // The first target is visited first
+        stack.extend(blocks[index].next.targets().into_iter().rev());
+    }
+    order
+}
+
+/// A path that leaves the line at `from` and joins it at the start of a block
+struct Jump<T: Tiling> {
+    from: Cursor<T>,
+    block: usize,
+    /// How many tiles along the line it leaves at
+    position: usize,
+}
+
+/// Compiles a Befunge-93 program, starting at the origin like a parsed program
+pub fn translate<T: Tiling>(
+    source: &str,
+    options: &BefungeOptions,
+) -> Result<LanguageState<T>, BefungeError>
+where
+    T::Edge: FollowableDirection,
+{
+    let blocks = blocks(&Grid::new(source))?;
+    let order = placement_order(&blocks);
+
+    // Which blocks are entered by going straight on from the block before them
+    let falls_through = |position: usize, target: usize| order.get(position + 1) == Some(&target);
+    let mut paths_joining = vec![0; blocks.len()];
+    for (position, &index) in order.iter().enumerate() {
+        match blocks[index].next {
+            Next::Go(target) | Next::Branch { zero: target, .. }
+                if falls_through(position, target) => {}
+            Next::Go(target) | Next::Branch { zero: target, .. } => paths_joining[target] += 1,
+            Next::Stop => {}
+        }
+        if let Next::Branch { other, .. } = blocks[index].next {
+            paths_joining[other] += 1;
+        }
+    }
+
+    let (mut layout, mut cursor) = Layout::new(Cursor::origin(), options.layout.clone());
+    let mut placed = 0;
+    let mut place = |layout: &mut Layout<T>, cursor: &mut Cursor<T>, character, sides: &[Side]| {
+        placed += 1;
+        Ok::<_, LayoutError>((placed, layout.place(cursor, character, sides)?))
+    };
+
+    let mut entries: Vec<Vec<(Cursor<T>, usize)>> = vec![vec![]; blocks.len()];
+    let mut jumps = vec![];
+    for (position, &index) in order.iter().enumerate() {
+        for _ in 0..paths_joining[index] {
+            place(&mut layout, &mut cursor, '0', &[])?;
+            let (at, mut exits) = place(&mut layout, &mut cursor, 'v', &[Side::MergeRight])?;
+            entries[index].push((exits.remove(0), at));
+        }
+        for character in blocks[index].code.chars() {
+            place(&mut layout, &mut cursor, character, &[])?;
+        }
+
+        let zero = match blocks[index].next {
+            Next::Go(target) => target,
+            Next::Branch { zero, other } => {
+                place(&mut layout, &mut cursor, ':', &[])?;
+                let (at, mut exits) = place(&mut layout, &mut cursor, 'v', &[Side::Right])?;
+                jumps.push(Jump {
+                    from: exits.remove(0),
+                    block: other,
+                    position: at,
+                });
+                place(&mut layout, &mut cursor, '~', &[])?;
+                zero
+            }
+            Next::Stop => {
+                place(&mut layout, &mut cursor, ';', &[])?;
+                continue;
+            }
+        };
+        if !falls_through(position, zero) {
+            place(&mut layout, &mut cursor, '1', &[])?;
+            let (at, mut exits) = place(&mut layout, &mut cursor, '>', &[Side::Right])?;
+            jumps.push(Jump {
+                from: exits.remove(0),
+                block: zero,
+                position: at,
+            });
+        }
+    }
+
+    // Each jump takes an entry of its block, the shorter paths are placed first so they stay
+    // close to the line
+    let mut arcs: Vec<_> = jumps
+        .into_iter()
+        .map(|jump| {
+            let (to, position) = entries[jump.block].pop().unwrap();
+            (jump.position.abs_diff(position), jump.from, to)
+        })
+        .collect();
+    arcs.sort_by_key(|(length, _, _)| *length);
+    for (_, from, to) in arcs {
+        layout.route(&from, &to)?;
+    }
+
+    Ok(layout.into_program())
+}
@@ -0,0 +1,232 @@
+//! Compiles Brainfuck to Painrose
+//!
+//! The tape is kept on the stack as a ring, with the current cell on top: `>` moves the top item
+//! to the bottom with `}`, `<` brings the bottom item back up with `{`. Cells hold any number
+//! rather than wrapping around at 256, and `,` stores 0 at the end of the input.
+//!
+//! The whole program is placed along one line, and loops are paths leaving and joining it on
+//! the same side:
+//!
+//! ```text
+//! :0=:vv body ::vv ...
+//!     ||      | |
+//!     |'------' |
+//!     '---------'
+//! ```
+//!
+//! `[` pushes whether the current cell is 0, and the first `v` leaves the line if it is. `]`
+//! leaves at its first `v` when the cell is not 0. Each path joins the line at the second `v` of
+//! the other bracket, which pops the 1 the path brought along and turns onto the line, while the
+//! line itself arrives there with a 0 and goes straight on. The paths of nested loops are placed
+//! first, so the paths of the loops around them go around them.
+//!
+//! Placing the paths can fail for large programs, see [`LayoutOptions::max_search`].
+use crate::geometry::tiling::Tiling;
+use crate::language::{FollowableDirection, LanguageState};
+use crate::layout::{Cursor, Layout, LayoutError, LayoutOptions, Side};
+
+#[derive(Clone, Debug)]
+pub struct BrainfuckOptions {
+    /// How many cells the tape has, the pointer wraps around at the ends
+    pub cells: usize,
+    pub layout: LayoutOptions,
+}
+
+impl Default for BrainfuckOptions {
+    fn default() -> Self {
+        BrainfuckOptions {
+            cells: 30_000,
+            layout: LayoutOptions::default(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BrainfuckError {
+    /// A `[` without a `]` or the other way around, at this character of the source
+    UnmatchedBracket {
+        position: usize,
+    },
+    Layout(LayoutError),
+}
+
+impl std::fmt::Display for BrainfuckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BrainfuckError::UnmatchedBracket { position } => {
+                write!(f, "unmatched bracket at character {position}")
+            }
+            BrainfuckError::Layout(error) => write!(f, "could not lay out the program: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for BrainfuckError {}
+
+impl From<LayoutError> for BrainfuckError {
+    fn from(error: LayoutError) -> Self {
+        BrainfuckError::Layout(error)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Op {
+    /// A run of `+` and `-`
+    Add(i64),
+    /// A run of `>` and `<`
+    Move(i64),
+    Output,
+    Input,
+    Loop(Vec<Op>),
+}
+
+fn parse(source: &str) -> Result<Vec<Op>, BrainfuckError> {
+    // The loops being parsed, with the position of their `[`
+    let mut open: Vec<(usize, Vec<Op>)> = vec![];
+    let mut ops = vec![];
+
+    for (position, character) in source.chars().enumerate() {
+        let op = match character {
+            '+' => Op::Add(1),
+            '-' => Op::Add(-1),
+            '>' => Op::Move(1),
+            '<' => Op::Move(-1),
+            '.' => Op::Output,
+            ',' => Op::Input,
+            '[' => {
+                open.push((position, std::mem::take(&mut ops)));
+                continue;
+            }
+            ']' => {
+                let (_, outer) = open
+                    .pop()
+                    .ok_or(BrainfuckError::UnmatchedBracket { position })?;
+                Op::Loop(std::mem::replace(&mut ops, outer))
+            }
+            _ => continue,
+        };
+
+        match (ops.last_mut(), op) {
+            (Some(Op::Add(total)), Op::Add(amount)) | (Some(Op::Move(total)), Op::Move(amount)) => {
+                *total += amount
+            }
+            (_, op) => ops.push(op),
+        }
+    }
+
+    match open.first() {
+        Some((position, _)) => Err(BrainfuckError::UnmatchedBracket {
+            position: *position,
+        }),
+        None => Ok(ops),
+    }
+}
+
+/// Code pushing a non-negative number
+fn number(value: u64) -> String {
+    if value < 10 {
+        value.to_string()
+    } else {
+        format!("${value}")
+    }
+}
+
+/// Code pushing `cells` zeros, doubling the zeros already pushed for every bit
+fn tape(cells: usize) -> String {
+    let cells = cells.max(1) as u64;
+    let mut code = "0".to_string();
+    let mut count = 1;
+    for bit in (0..(u64::BITS - cells.leading_zeros() - 1)).rev() {
+        code += &number(count);
+        code.push('d');
+        count *= 2;
+        if cells & (1 << bit) != 0 {
+            code.push('0');
+            count += 1;
+        }
+    }
+    code
+}
+
+/// The code for everything but loops
+fn code(op: &Op) -> String {
+    match op {
+        Op::Add(0) | Op::Move(0) => String::new(),
+        Op::Add(amount) if *amount > 0 => number(amount.unsigned_abs()) + "+",
+        Op::Add(amount) => number(amount.unsigned_abs()) + "_+",
+        Op::Move(amount) if *amount > 0 => "}".repeat(amount.unsigned_abs() as usize),
+        Op::Move(amount) => "{".repeat(amount.unsigned_abs() as usize),
+        Op::Output => ":I".to_string(),
+        Op::Input => "~i:0)*".to_string(),
+        Op::Loop(_) => unreachable!("loops are placed by `emit`"),
+    }
+}
+
+/// A path that leaves the line at `from` and joins it again at `to`
+struct Arc<T: Tiling> {
+    from: Cursor<T>,
+    to: Cursor<T>,
+}
+
+/// Places the ops along the line, collecting the arcs of the loops. The arcs of a loop come after
+/// the arcs of the loops inside it.
+fn emit<T: Tiling>(
+    layout: &mut Layout<T>,
+    cursor: &mut Cursor<T>,
+    ops: &[Op],
+    arcs: &mut Vec<Arc<T>>,
+) -> Result<(), LayoutError>
+where
+    T::Edge: FollowableDirection,
+{
+    for op in ops {
+        let Op::Loop(body) = op else {
+            layout.place_str(cursor, &code(op))?;
+            continue;
+        };
+
+        layout.place_str(cursor, ":0=:")?;
+        let skip = layout.place(cursor, 'v', &[Side::Right])?.remove(0);
+        let repeat_target = layout.place(cursor, 'v', &[Side::MergeRight])?.remove(0);
+
+        emit(layout, cursor, body, arcs)?;
+
+        layout.place_str(cursor, "::")?;
+        let repeat = layout.place(cursor, 'v', &[Side::Right])?.remove(0);
+        let skip_target = layout.place(cursor, 'v', &[Side::MergeRight])?.remove(0);
+
+        arcs.push(Arc {
+            from: repeat,
+            to: repeat_target,
+        });
+        arcs.push(Arc {
+            from: skip,
+            to: skip_target,
+        });
+    }
+    Ok(())
+}
+
+/// Compiles a Brainfuck program, starting at the origin like a parsed program. Characters other
+/// than the eight commands are ignored.
+pub fn translate<T: Tiling>(
+    source: &str,
+    options: &BrainfuckOptions,
+) -> Result<LanguageState<T>, BrainfuckError>
+where
+    T::Edge: FollowableDirection,
+{
+    let ops = parse(source)?;
+
+    let (mut layout, mut cursor) = Layout::new(Cursor::origin(), options.layout.clone());
+    layout.place_str(&mut cursor, &tape(options.cells))?;
+    let mut arcs = vec![];
+    emit(&mut layout, &mut cursor, &ops, &mut arcs)?;
+    layout.place_str(&mut cursor, ";")?;
+
+    for Arc { from, to } in arcs {
+        layout.route(&from, &to)?;
+    }
+
+    Ok(layout.into_program())
+}
@@ -0,0 +1,3 @@
+//! Translates programs in other languages to Painrose, placing the code with [`crate::layout`]
+pub mod befunge;
+pub mod brainfuck;
@@ -0,0 +1,174 @@
+use painrose_lib::analysis::cfg::{AnalysisOptions, ControlFlowGraph, StaticMode, Terminator};
+use painrose_lib::analysis::stack::{check_stack, StackWarning};
+use painrose_lib::geometry::rhomb::RhombTiling;
+use painrose_lib::language::LanguageState;
+
+fn build(code: &str) -> ControlFlowGraph<RhombTiling> {
+    let program = LanguageState::<RhombTiling>::new_from_string(code.to_string()).unwrap();
+    ControlFlowGraph::build(&program, &AnalysisOptions::default())
+}
+
+#[test]
+fn test_straight_program() {
+    let graph = build(":1N;");
+    assert_eq!(graph.blocks.len(), 1);
+    assert_eq!(graph.blocks[0].states.len(), 3);
+    assert_eq!(graph.blocks[0].terminator, Terminator::Stop);
+    assert_eq!(graph.reachable_tiles().len(), 3);
+}
+
+#[test]
+fn test_strings_are_not_instructions() {
+    let graph = build(":\"v?\\\"\"W;");
+    assert_eq!(graph.blocks.len(), 1);
+    assert_eq!(graph.blocks[0].terminator, Terminator::Stop);
+    assert_eq!(graph.blocks[0].states[1].mode, StaticMode::ArrayString);
+    assert!(matches!(
+        graph.blocks[0].states[4].mode,
+        StaticMode::Escape(..)
+    ));
+
+    assert_eq!(
+        build(":\"\\q\"W;").blocks[0].terminator,
+        Terminator::RuntimeError
+    );
+}
+
+#[test]
+fn test_branches() {
+    let graph = build(":1v2N;");
+    let Terminator::Branch { straight, turned } = graph.blocks[graph.entry].terminator else {
+        panic!("Expected a branch, got {:?}", graph.blocks[0].terminator);
+    };
+    assert_eq!(graph.blocks[straight].terminator, Terminator::Stop);
+    assert_eq!(graph.blocks[straight].states.len(), 3);
+    // Turning leads into empty tiles
+    assert_eq!(graph.blocks[turned].terminator, Terminator::LeftProgram);
+    assert_eq!(graph.successors(graph.entry), vec![straight, turned]);
+
+    let graph = build(":?1;");
+    assert!(matches!(
+        graph.blocks[0].terminator,
+        Terminator::Guard { .. }
+    ));
+}
+
+#[test]
+fn test_starts_at_the_entry() {
+    let mut program = LanguageState::<RhombTiling>::new_from_string(":1N;".to_string()).unwrap();
+    program.step(&mut vec![], &mut &b""[..]);
+    let graph = ControlFlowGraph::build(&program, &AnalysisOptions::default());
+    let start = &graph.blocks[graph.entry].states[0];
+    assert_eq!(&start.tile, program.get_entry().0);
+    assert_ne!(&start.tile, program.get_instruction_pointer());
+    assert_eq!(graph.blocks[graph.entry].states.len(), 3);
+}
+
+#[test]
+fn test_empty_paths_end() {
+    let program = LanguageState::<RhombTiling>::new_from_string(":1".to_string()).unwrap();
+    let graph = ControlFlowGraph::build(&program, &AnalysisOptions { max_empty_steps: 5 });
+    assert_eq!(graph.blocks.len(), 1);
+    assert_eq!(graph.blocks[0].terminator, Terminator::LeftProgram);
+    assert_eq!(graph.blocks[0].states.len(), 2 + 5);
+}
+
+#[test]
+fn test_every_block_is_reachable() {
+    let graph = build(":1v2^3N;\nCAE-s:?v4<5;");
+    let mut seen = vec![false; graph.blocks.len()];
+    let mut stack = vec![graph.entry];
+    while let Some(block) = stack.pop() {
+        if !std::mem::replace(&mut seen[block], true) {
+            stack.extend(graph.successors(block));
+        }
+    }
+    assert!(seen.into_iter().all(|seen| seen));
+}
+
+fn stack_warnings(code: &str) -> Vec<StackWarning<RhombTiling>> {
+    let program = LanguageState::<RhombTiling>::new_from_string(code.to_string()).unwrap();
+    let graph = ControlFlowGraph::build(&program, &AnalysisOptions::default());
+    check_stack(&program, &graph)
+}
+
+#[test]
+fn test_stack_underflow() {
+    let warnings = stack_warnings(":+N;");
+    assert_eq!(warnings.len(), 1);
+    let StackWarning::Underflow {
+        tile,
+        character,
+        needed,
+        available,
+    } = &warnings[0]
+    else {
+        panic!("Expected an underflow, got {warnings:?}");
+    };
+    assert_eq!(tile.len(), 0);
+    assert_eq!((*character, *needed, *available), ('+', 2, 0));
+
+    assert!(stack_warnings(":12+N;").is_empty());
+    assert!(stack_warnings(":'ab'+N;").is_empty());
+    // `"` pushes a single array
+    assert_eq!(stack_warnings(":\"ab\"+N;").len(), 1);
+    assert!(stack_warnings(":$12.5~;").is_empty());
+    // `B` starts with an empty stack
+    assert!(matches!(
+        stack_warnings(":12B+;")[..],
+        [StackWarning::Underflow { needed: 2, .. }]
+    ));
+    // The effect of `a` depends on the values, so the depth is unknown afterwards
+    assert!(stack_warnings(":1a+;").is_empty());
+}
+
+#[test]
+fn test_unbounded_growth() {
+    // The instruction pointer bounces between the two `|`, pushing a 1 every time it passes
+    let warnings = stack_warnings(":1|\n#1/s:|");
+    let [StackWarning::UnboundedGrowth { tiles }] = &warnings[..] else {
+        panic!("Expected unbounded growth, got {warnings:?}");
+    };
+    assert_eq!(tiles.len(), 3);
+
+    // Going back and forth pushes a 1 and pops it twice
+    assert!(matches!(
+        stack_warnings(":1~|\n#1/s:|")[..],
+        [StackWarning::Underflow { character: '~', .. }]
+    ));
+
+    // A loop that only pops keeps the stack from growing, however often it goes around
+    let warnings = stack_warnings(":01234567890vN:\nACCCAACAE-w:v~\nCCACAACAE:>\nEBEBCCCAE:<\n");
+    assert!(!warnings.is_empty());
+    assert!(!warnings
+        .iter()
+        .any(|warning| matches!(warning, StackWarning::UnboundedGrowth { .. })));
+}
+
+#[test]
+fn test_array_access_may_not_push() {
+    // With a number instead of an array `[` pops both and pushes nothing
+    assert!(matches!(
+        stack_warnings(":20[N;")[..],
+        [StackWarning::Underflow {
+            character: 'N',
+            needed: 1,
+            available: 0,
+            ..
+        }]
+    ));
+    assert!(matches!(
+        stack_warnings(":210]N;")[..],
+        [StackWarning::Underflow { character: 'N', .. }]
+    ));
+    assert!(stack_warnings(":120[N;").is_empty());
+}
+
+#[test]
+fn test_copy_has_a_fixed_effect() {
+    assert!(stack_warnings(":12 1cN;").is_empty());
+    assert!(matches!(
+        stack_warnings(":1c+;")[..],
+        [StackWarning::Underflow { character: '+', .. }]
+    ));
+}
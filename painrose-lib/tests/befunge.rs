@@ -0,0 +1,99 @@
+mod common;
+
+use painrose_lib::geometry::rhomb::RhombTiling;
+use painrose_lib::language::{LanguageState, RunOutcome};
+use painrose_lib::translate::befunge::{translate, BefungeError, BefungeOptions, Untranslatable};
+
+const HELLO_WORLD: &str = "\"!dlroW ,olleH\">:#,_@";
+
+const FACTORIAL: &str = "&>:1-:v v *_$.@\n ^    _$>\\:^";
+
+fn translated(source: &str) -> LanguageState<RhombTiling> {
+    translate(source, &BefungeOptions::default()).unwrap()
+}
+
+fn run_with_input(source: &str, input: &str) -> (String, Option<RunOutcome>) {
+    common::run_source(&translated(source).serialize(), input)
+}
+
+#[test]
+fn test_hello_world() {
+    assert_eq!(
+        run_with_input(HELLO_WORLD, ""),
+        ("Hello, World!".to_string(), Some(RunOutcome::Quit))
+    );
+}
+
+#[test]
+fn test_arithmetic() {
+    assert_eq!(run_with_input("93-.@", "").0, "6 ");
+    assert_eq!(run_with_input("43`.34`.@", "").0, "1 0 ");
+    assert_eq!(run_with_input("0!.5!.@", "").0, "1 0 ");
+    assert_eq!(run_with_input("12\\..@", "").0, "1 2 ");
+    // Popping the empty stack gives 0
+    assert_eq!(run_with_input("..@", "").0, "0 0 ");
+}
+
+#[test]
+fn test_division_rounds_towards_zero() {
+    assert_eq!(run_with_input("72/.72%.@", "").0, "3 1 ");
+    assert_eq!(run_with_input("07-2/.07-2%.@", "").0, "-3 -1 ");
+    assert_eq!(run_with_input("702-/.702-%.@", "").0, "-3 1 ");
+    assert_eq!(run_with_input("93/.93%.@", "").0, "3 0 ");
+}
+
+#[test]
+fn test_strings() {
+    assert_eq!(run_with_input("\"a b\",,,@", "").0, "b a");
+    assert_eq!(run_with_input("\"\\\\\",,@", "").0, "\\\\");
+}
+
+#[test]
+fn test_loops() {
+    assert_eq!(run_with_input("5>:.1-:v\n ^     _@", "").0, "5 4 3 2 1 ");
+    assert_eq!(run_with_input(FACTORIAL, "5").0, "120 ");
+}
+
+#[test]
+fn test_input() {
+    // The line wraps around to read the next character
+    assert_eq!(run_with_input("~:1+!#@_,", "echo").0, "echo");
+    assert_eq!(run_with_input("&&+.@", "3 4").0, "7 ");
+}
+
+#[test]
+fn test_untranslatable_cells_are_reported() {
+    assert_eq!(
+        translate::<RhombTiling>("g?@\n  p", &BefungeOptions::default()).err(),
+        Some(BefungeError::Untranslatable(vec![Untranslatable {
+            line: 0,
+            column: 0,
+            character: 'g'
+        }]))
+    );
+    assert_eq!(
+        translate::<RhombTiling>(" v\n?_p", &BefungeOptions::default()).err(),
+        Some(BefungeError::Untranslatable(vec![
+            Untranslatable {
+                line: 1,
+                column: 0,
+                character: '?'
+            },
+            Untranslatable {
+                line: 1,
+                column: 2,
+                character: 'p'
+            }
+        ]))
+    );
+    // Cells the program never reaches don't matter
+    assert!(translate::<RhombTiling>("@p", &BefungeOptions::default()).is_ok());
+}
+
+#[test]
+fn test_engines_agree_on_translated_programs() {
+    common::assert_engines_agree([
+        (translated(HELLO_WORLD), &b""[..]),
+        (translated(FACTORIAL), b"6"),
+    ]);
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use painrose_lib::geometry::rhomb::RhombTiling;
+use painrose_lib::language::{LanguageState, RunOutcome};
+use painrose_lib::translate::brainfuck::{translate, BrainfuckError, BrainfuckOptions};
+
+const HELLO_WORLD: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+fn options() -> BrainfuckOptions {
+    BrainfuckOptions {
+        cells: 16,
+        ..Default::default()
+    }
+}
+
+fn translated(source: &str) -> LanguageState<RhombTiling> {
+    translate(source, &options()).unwrap()
+}
+
+fn run_with_input(source: &str, input: &str) -> (String, Option<RunOutcome>) {
+    common::run_source(&translated(source).serialize(), input)
+}
+
+#[test]
+fn test_hello_world() {
+    assert_eq!(
+        run_with_input(HELLO_WORLD, ""),
+        ("Hello World!\n".to_string(), Some(RunOutcome::Quit))
+    );
+}
+
+#[test]
+fn test_loop() {
+    assert_eq!(run_with_input("+++++++[>+++++++<-]>.", "").0, "1");
+    // A loop that is never entered
+    assert_eq!(run_with_input("[-]++++++++[->++++++<]>.", "").0, "0");
+}
+
+#[test]
+fn test_nested_loops() {
+    assert_eq!(run_with_input("+++[>+++[>++++++<-]<-]>>+++.", "").0, "9");
+}
+
+#[test]
+fn test_input() {
+    assert_eq!(run_with_input(",[.,]", "echo").0, "echo");
+    // The end of the input reads as 0
+    assert_eq!(
+        run_with_input(",,++++++++++++++++++++++++++++++++++++++++++++++++.", "a").0,
+        "0"
+    );
+}
+
+#[test]
+fn test_tape_wraps_around() {
+    assert_eq!(run_with_input("<++++++++[->>++++++<<]>>.", "").0, "0");
+}
+
+#[test]
+fn test_unmatched_brackets() {
+    assert_eq!(
+        translate::<RhombTiling>("+[[-]", &options()).err(),
+        Some(BrainfuckError::UnmatchedBracket { position: 1 })
+    );
+    assert_eq!(
+        translate::<RhombTiling>("+]", &options()).err(),
+        Some(BrainfuckError::UnmatchedBracket { position: 1 })
+    );
+}
+
+#[test]
+fn test_engines_agree_on_translated_programs() {
+    common::assert_engines_agree([
+        (translated(HELLO_WORLD), &b""[..]),
+        (translated(",[.,]"), b"echo"),
+    ]);
+}
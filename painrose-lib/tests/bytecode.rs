@@ -0,0 +1,93 @@
+use painrose_lib::analysis::cfg::AnalysisOptions;
+use painrose_lib::bytecode::differential::{compare, DifferentialOptions};
+use painrose_lib::bytecode::vm::Vm;
+use painrose_lib::bytecode::Bytecode;
+use painrose_lib::geometry::rhomb::RhombTiling;
+use painrose_lib::language::error::RuntimeError;
+use painrose_lib::language::{LanguageState, RunOutcome};
+
+fn program(code: &str) -> LanguageState<RhombTiling> {
+    LanguageState::new_from_string(code.to_string()).unwrap()
+}
+
+fn run_vm(code: &str, input: &str) -> (String, Option<RunOutcome>) {
+    let bytecode = Bytecode::compile(&program(code), &AnalysisOptions::default());
+    let mut vm = Vm::new(&bytecode);
+    let mut output = vec![];
+    let mut input = input.as_bytes();
+    while vm.is_running() {
+        vm.step(&mut output, &mut input);
+    }
+    (String::from_utf8(output).unwrap(), vm.get_outcome())
+}
+
+#[test]
+fn test_straight_program() {
+    let bytecode = Bytecode::compile(&program(":1N;"), &AnalysisOptions::default());
+    assert_eq!(
+        bytecode.to_string(),
+        "0 execute Const(1)\n1 execute OutputNumber\n2 execute Quit\n"
+    );
+    assert_eq!(bytecode.get_tile(2), &"DBEDDB".parse().unwrap());
+
+    assert_eq!(
+        run_vm(":1N;", ""),
+        ("1 ".to_string(), Some(RunOutcome::Quit))
+    );
+}
+
+#[test]
+fn test_vm_literals() {
+    assert_eq!(run_vm(":\"Hello World!\"I;", "").0, "Hello World!");
+    assert_eq!(run_vm(":'ab'II;", "").0, "ba");
+    assert_eq!(run_vm(":`\\nN;", "").0, "10 ");
+    assert_eq!(run_vm(":$12.5N$3.2.N;", "").0, "12.5 3.2 ");
+    assert_eq!(
+        run_vm(":\"\\q\"I;", "").1,
+        Some(RunOutcome::RuntimeError(RuntimeError::InvalidEscape))
+    );
+    assert_eq!(
+        run_vm(":$N;", "").1,
+        Some(RunOutcome::RuntimeError(RuntimeError::InvalidNumber))
+    );
+}
+
+#[test]
+fn test_vm_control_flow() {
+    // The guard catches the division by zero and turns right into the second line
+    assert_eq!(
+        run_vm(":?01/;\n#1/e:N;", ""),
+        ("1 ".to_string(), Some(RunOutcome::Quit))
+    );
+    assert_eq!(run_vm(":nn+N;", "2 3").0, "5 ");
+    assert_eq!(run_vm(":5Q", "").1, Some(RunOutcome::Exit(5)));
+    assert_eq!(run_vm(":1", "").1, Some(RunOutcome::LeftProgram));
+}
+
+#[test]
+fn test_engines_agree() {
+    for (code, input) in [
+        (":\"Hello World!\"I;", ""),
+        (":\"ab\n+/nn:c\"I;", ""),
+        (":'ab\n+/nn:c'III;", ""),
+        (":$12\n+/nn:3N;", ""),
+        (":1v2N;\nCAE-s:?v4<5;", ""),
+        (":?01/;\n#1/e:N;", ""),
+        (":1N01/N;", ""),
+        (":nnN;", "4 5"),
+        (":iiII;", "ab"),
+        (":1~|\n#1/s:|", ""),
+        (":1N|\n#1/s:|", ""),
+        (":1B232ENNN;", ""),
+    ] {
+        assert_eq!(
+            compare(
+                program(code),
+                input.as_bytes(),
+                &DifferentialOptions::default()
+            ),
+            None,
+            "engines disagree on {code:?}"
+        );
+    }
+}
@@ -0,0 +1,36 @@
+//! Helpers for the tests of generated programs
+#![allow(dead_code)]
+
+use painrose_lib::bytecode::differential::{compare, DifferentialOptions};
+use painrose_lib::geometry::rhomb::RhombTiling;
+use painrose_lib::language::{LanguageState, RunOutcome};
+
+/// Parses and runs a program for up to 100_000 steps, returning its output and how it stopped.
+/// Generated programs are run from their source code, like a `.pr` file written by the CLI.
+pub fn run_source(source: &str, input: &str) -> (String, Option<RunOutcome>) {
+    let mut program = LanguageState::<RhombTiling>::new_from_string(source.to_string()).unwrap();
+    program.compile();
+    let mut output = vec![];
+    let mut input = input.as_bytes();
+
+    for _ in 0..100_000 {
+        if !program.is_running() {
+            break;
+        }
+        program.step(&mut output, &mut input);
+    }
+
+    (String::from_utf8(output).unwrap(), program.get_outcome())
+}
+
+/// Checks that the interpreter and the bytecode VM agree on every program with its input
+pub fn assert_engines_agree<'a>(
+    cases: impl IntoIterator<Item = (LanguageState<RhombTiling>, &'a [u8])>,
+) {
+    for (program, input) in cases {
+        assert_eq!(
+            compare(program, input, &DifferentialOptions::default()),
+            None
+        );
+    }
+}
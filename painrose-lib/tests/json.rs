@@ -0,0 +1,90 @@
+use painrose_lib::geometry::rhomb::RhombTiling;
+use painrose_lib::language::error::JsonError;
+use painrose_lib::language::json::{json_to_source, source_to_json};
+use painrose_lib::language::LanguageState;
+
+#[test]
+fn test_write_json() {
+    let json = source_to_json::<RhombTiling>("!entry CAE-s\nCAE-s:1N;").unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({
+            "tiling": "rhomb",
+            "entry": {"coordinate": "CAE", "direction": "s"},
+            "tiles": [
+                {"coordinate": "CAE", "character": "1"},
+                {"coordinate": "BACCEB", "character": "N"},
+                {"coordinate": "CCCCEB", "character": ";"},
+            ]
+        })
+    );
+}
+
+#[test]
+fn test_json_round_trip() {
+    let source = ":?01/;\nCAE-s:N;\n";
+    let json = source_to_json::<RhombTiling>(source).unwrap();
+    assert_eq!(
+        json_to_source::<RhombTiling>(&json).unwrap(),
+        LanguageState::<RhombTiling>::new_from_string(source.to_string())
+            .unwrap()
+            .serialize()
+    );
+}
+
+#[test]
+fn test_read_json() {
+    let json = source_to_json::<RhombTiling>(":2N;").unwrap();
+    let mut program = LanguageState::<RhombTiling>::new_from_json(&json).unwrap();
+    let mut output = vec![];
+    while program.is_running() {
+        program.step(&mut output, &mut std::io::empty());
+    }
+    assert_eq!(String::from_utf8(output).unwrap(), "2 ");
+}
+
+#[test]
+fn test_json_errors() {
+    let read = |json: &str| LanguageState::<RhombTiling>::new_from_json(json).unwrap_err();
+
+    assert!(matches!(read("{"), JsonError::SyntaxError(_)));
+    assert!(matches!(
+        read(r#"{"tiling": "square", "tiles": []}"#),
+        JsonError::WrongTilingError { .. }
+    ));
+    assert!(matches!(
+        read(r#"{"tiling": "rhomb", "tiles": [{"coordinate": "x", "character": "1"}]}"#),
+        JsonError::BadCoordinateError { .. }
+    ));
+    assert!(matches!(
+        read(r#"{"tiling": "rhomb", "entry": {"coordinate": "", "direction": "up"}, "tiles": []}"#),
+        JsonError::BadDirectionError(_)
+    ));
+    let error = read(
+        r#"{"tiling": "rhomb", "tiles": [{"coordinate": "A", "character": "1"}, {"coordinate": "A", "character": "2"}]}"#,
+    );
+    assert_eq!(error.to_string(), "the tile `A` is listed more than once");
+    for character in [r"\n", r"\r"] {
+        let error = read(&format!(
+            r#"{{"tiling": "rhomb", "tiles": [{{"coordinate": "AAE", "character": "{character}"}}]}}"#
+        ));
+        assert!(matches!(error, JsonError::LineBreakError(_)));
+    }
+}
+
+#[test]
+fn test_json_round_trip_keeps_every_tile() {
+    let json = r#"{"tiling": "rhomb", "tiles": [{"coordinate": "AAE", "character": "\t"}, {"coordinate": "CAE", "character": "1"}]}"#;
+    let source = json_to_source::<RhombTiling>(json).unwrap();
+    let round_trip = source_to_json::<RhombTiling>(&source).unwrap();
+    assert_eq!(
+        LanguageState::<RhombTiling>::new_from_json(&round_trip)
+            .unwrap()
+            .to_json(),
+        LanguageState::<RhombTiling>::new_from_json(json)
+            .unwrap()
+            .to_json()
+    );
+    assert!(json_to_source::<RhombTiling>(&json.replace(r"\t", r"\r")).is_err());
+}
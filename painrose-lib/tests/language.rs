@@ -0,0 +1,284 @@
+use painrose_lib::geometry::rhomb::{AbsoluteDirection, RhombTiling};
+use painrose_lib::geometry::tile_coordinate::TileCoordinate;
+use painrose_lib::language::error::RuntimeError;
+use painrose_lib::language::format::{Notation, NumberFormat};
+use painrose_lib::language::loop_detection::LoopDetection;
+use painrose_lib::language::parser::ParseOptions;
+use painrose_lib::language::{FollowableDirection, LanguageState, RunOutcome};
+
+fn run_with_input(code: &str, input: &str) -> (String, LanguageState<RhombTiling>) {
+    let mut program = LanguageState::<RhombTiling>::new_from_string(code.to_string()).unwrap();
+    let mut output = vec![];
+    let mut input = input.as_bytes();
+
+    while program.is_running() {
+        program.step(&mut output, &mut input);
+    }
+
+    (String::from_utf8(output).unwrap(), program)
+}
+
+fn run(code: &str) -> String {
+    run_with_input(code, "").0
+}
+
+#[test]
+fn test_hello_world() {
+    assert_eq!(run(":\"Hello World!\"I;"), "Hello World!");
+}
+
+#[test]
+fn test_swap() {
+    assert_eq!(run(":12sNN;"), "1 2 ");
+    assert_eq!(run(":1sNN;"), "0 1 ");
+}
+
+#[test]
+fn test_stack_of_stacks() {
+    assert_eq!(run(":1B232ENNN;"), "3 2 1 ");
+    assert_eq!(run(":1B2XN;"), "1 ");
+    assert_eq!(run(":12B3XNN;"), "2 1 ");
+    // `E` only moves the elements the stack has
+    assert_eq!(run(":1B25ENN;"), "2 1 ");
+    assert_eq!(run(":?B\"ab\"E;\nCAE-s:N;"), "3 ");
+    // Rotating a new, empty stack does nothing
+    assert_eq!(run(":B{1N;"), "1 ");
+    assert_eq!(run(":B}1N;"), "1 ");
+}
+
+#[test]
+fn test_runtime_error_stops_program() {
+    let (output, program) = run_with_input(":1N01/N;", "");
+    assert_eq!(output, "1 ");
+    assert_eq!(
+        program.get_outcome(),
+        Some(RunOutcome::RuntimeError(RuntimeError::DivisionByZero))
+    );
+}
+
+#[test]
+fn test_guard_catches_error() {
+    // After the error the IP leaves the guard at "" to the east, entering CAE from the north
+    assert_eq!(run(":?01/;\nCAE-s:N;"), "1 ");
+    assert_eq!(run(":?&01/;\nCAE-s:N;"), "");
+    assert_eq!(run(":?\"ab\"3[;\nCAE-s:N;"), "2 ");
+}
+
+#[test]
+fn test_input() {
+    assert_eq!(run_with_input(":nn+N;", "12 30.5").0, "42.5 ");
+    assert_eq!(run_with_input(":iNiNiN;", "aé").0, "97 233 -1 ");
+    assert_eq!(run_with_input(":wI;", "one line\nnext").0, "one line");
+    assert_eq!(run_with_input(":?n;\nCAE-s:N;", "abc").0, "3 ");
+}
+
+#[test]
+fn test_escape_sequences() {
+    assert_eq!(run(r#":"a\nb\"c"I;"#), "a\nb\"c");
+    assert_eq!(run(r":'\u{41}\''II;"), "'A");
+    assert_eq!(run(r":`\tN;"), "9 ");
+
+    let (_, program) = run_with_input(r#":"\q";"#, "");
+    assert_eq!(
+        program.get_outcome(),
+        Some(RunOutcome::RuntimeError(RuntimeError::InvalidEscape))
+    );
+}
+
+#[test]
+fn test_number_literals() {
+    assert_eq!(run(":$12.5$3+N;"), "15.5 ");
+    assert_eq!(run(":$100N$1.2.N;"), "100 1.2 ");
+
+    for code in [":$N;", ":$.N;"] {
+        let (output, program) = run_with_input(code, "");
+        assert_eq!(output, "");
+        assert_eq!(
+            program.get_outcome(),
+            Some(RunOutcome::RuntimeError(RuntimeError::InvalidNumber))
+        );
+    }
+}
+
+#[test]
+fn test_exit_codes() {
+    let (_, program) = run_with_input(":;", "");
+    assert_eq!(program.get_outcome(), Some(RunOutcome::Quit));
+    assert_eq!(program.get_outcome().unwrap().exit_code(), 0);
+
+    let (_, program) = run_with_input(":$42Q", "");
+    assert_eq!(program.get_outcome(), Some(RunOutcome::Exit(42)));
+    assert_eq!(program.get_outcome().unwrap().exit_code(), 42);
+
+    let (_, program) = run_with_input(":$255.9Q", "");
+    assert_eq!(program.get_outcome(), Some(RunOutcome::Exit(255)));
+
+    // Codes that aren't a valid process status are runtime errors, so they exit with 1
+    for code in [":\"ab\"Q", ":$256Q", ":1_Q"] {
+        let (_, program) = run_with_input(code, "");
+        assert_eq!(
+            program.get_outcome(),
+            Some(RunOutcome::RuntimeError(RuntimeError::InvalidExitCode))
+        );
+        assert_eq!(program.get_outcome().unwrap().exit_code(), 1);
+    }
+}
+
+#[test]
+fn test_number_format_instruction() {
+    assert_eq!(run(":$3.14159 22FN;"), "3.14 ");
+    assert_eq!(run(":$2.7 1FN;"), "2 ");
+    assert_eq!(run(":$1500:23FN0FN;"), "1.50e3 1500 ");
+    // Structured arrays and the separator don't change the notation
+    assert_eq!(run(":\", \"F4F$1.5 1F\"ab\"1:N2aN;"), "1, [[97, 98], 1], ");
+    assert_eq!(run(":\"\\n\"F4F5F\"ab\"N;"), "97\n98\n");
+}
+
+#[test]
+fn test_structured_number_format() {
+    let mut program =
+        LanguageState::<RhombTiling>::new_from_string(":\"ab\"1:N2aN;".to_string()).unwrap();
+    program.set_number_format(NumberFormat {
+        notation: Notation::Fixed(1),
+        separator: "\n".to_string(),
+        structured_arrays: true,
+    });
+
+    let mut output = vec![];
+    while program.is_running() {
+        program.step(&mut output, &mut std::io::empty());
+    }
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "1.0\n[[97.0, 98.0], 1.0]\n"
+    );
+}
+
+#[test]
+fn test_relative_prefix_program() {
+    assert_eq!(run(":?01/;\n#1/e:N;"), "1 ");
+}
+
+#[test]
+fn test_comments_and_entry() {
+    let code = "!entry @main-s\n\n// Starts at @main instead of the origin\n@main=CAE\n:1N;\n  \n@main-s:2N;";
+    assert_eq!(run(code), "2 ");
+}
+
+#[test]
+fn test_included_program() {
+    let options = ParseOptions {
+        base_directory: Some(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/programs").into()),
+        ..ParseOptions::default()
+    };
+    let parsed =
+        LanguageState::<RhombTiling>::parse("!include print_two.pr 4 C\n!entry CEDBC-s", &options)
+            .unwrap();
+
+    let mut program = parsed.state;
+    let mut output = vec![];
+    while program.is_running() {
+        program.step(&mut output, &mut std::io::empty());
+    }
+    assert_eq!(String::from_utf8(output).unwrap(), "2 ");
+}
+
+fn run_detecting_loops(code: &str, max_steps: usize) -> LanguageState<RhombTiling> {
+    let mut program = LanguageState::<RhombTiling>::new_from_string(code.to_string()).unwrap();
+    program.set_loop_detection(Some(LoopDetection {
+        max_empty_steps: 10,
+    }));
+
+    let mut output = vec![];
+    for _ in 0..max_steps {
+        if !program.is_running() {
+            break;
+        }
+        program.step(&mut output, &mut std::io::empty());
+    }
+    program
+}
+
+#[test]
+fn test_infinite_loop() {
+    // Bounces between the two `|`, pushing and popping a 1 on the way
+    let program = run_detecting_loops(":1~|\n#1/s:|", 1000);
+    assert_eq!(
+        program.get_outcome(),
+        Some(RunOutcome::InfiniteLoop { length: 6 })
+    );
+    assert_eq!(program.get_loop_tiles().len(), 4);
+    assert_eq!(program.get_outcome().unwrap().exit_code(), 1);
+
+    // Printing every time around is not an infinite loop
+    let program = run_detecting_loops(":1N~|\n#1/s:|", 1000);
+    assert!(program.is_running());
+
+    // The stack grows every time around, so the program never gets back to the same state
+    let program = run_detecting_loops(":1|\n#1/s:|", 100_000);
+    assert!(program.is_running());
+}
+
+#[test]
+fn test_left_program() {
+    let program = run_detecting_loops(":1", 1000);
+    assert_eq!(program.get_outcome(), Some(RunOutcome::LeftProgram));
+    assert!(program.get_loop_tiles().is_empty());
+}
+
+/// Runs a program, returning its output, outcome and the number of steps it took
+fn run_counting_steps(code: &str, compiled: bool) -> (Vec<u8>, Option<RunOutcome>, usize) {
+    let mut program = LanguageState::<RhombTiling>::new_from_string(code.to_string()).unwrap();
+    if compiled {
+        program.compile();
+    }
+
+    let mut output = vec![];
+    let mut input = "12 ab".as_bytes();
+    let mut steps = 0;
+    while program.is_running() && steps < 10000 {
+        program.step(&mut output, &mut input);
+        steps += 1;
+    }
+    (output, program.get_outcome(), steps)
+}
+
+#[test]
+fn test_compiled_program_behaves_the_same() {
+    for code in [
+        ":\"Hello World!\"I;",
+        // Strings and numbers continue over the empty tiles between lines
+        ":\"ab\n+/nn:c\"I;",
+        ":'ab\n+/nn:c'III;",
+        ":12\n+/nn:3N;",
+        ":1v2N;\nCAE-s:?v4<5;",
+        ":?01/;\n#1/e:N;",
+        ":1N01/N;",
+        ":nnN;",
+        ":1\n+/nnn:N",
+    ] {
+        let (output, outcome, _) = run_counting_steps(code, false);
+        let (compiled_output, compiled_outcome, _) = run_counting_steps(code, true);
+        assert_eq!(output, compiled_output, "output of {code:?}");
+        assert_eq!(outcome, compiled_outcome, "outcome of {code:?}");
+    }
+}
+
+#[test]
+fn test_compiled_program_skips_empty_tiles() {
+    // Leaves six empty tiles between the two lines
+    let mut tile = TileCoordinate::<RhombTiling>::new(vec![]).unwrap();
+    let mut direction = AbsoluteDirection::North;
+    for _ in 0..8 {
+        let (next, entry) = tile.go(direction).unwrap();
+        (tile, direction) = (next, entry.opposite());
+    }
+    let code = &format!(":1N\n{tile}-{direction}:2N;");
+
+    let (output, _, steps) = run_counting_steps(code, false);
+    let (compiled_output, _, compiled_steps) = run_counting_steps(code, true);
+    assert_eq!(compiled_output, b"1 2 ");
+    assert_eq!(output, compiled_output);
+    assert_eq!(steps - compiled_steps, 6);
+}
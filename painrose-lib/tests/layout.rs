@@ -0,0 +1,100 @@
+mod common;
+
+use std::ops::Range;
+
+use painrose_lib::geometry::rhomb::RhombTiling;
+use painrose_lib::language::LanguageState;
+use painrose_lib::layout::{lay_out, LayoutError, LayoutOptions};
+
+fn run(program: LanguageState<RhombTiling>) -> String {
+    common::run_source(&program.serialize(), "").0
+}
+
+#[test]
+fn test_straight_line() {
+    let program = lay_out(":`i`HII;", &[], &LayoutOptions::default()).unwrap();
+    assert_eq!(run(program), "Hi");
+}
+
+#[test]
+fn test_loop() {
+    // Counts down from 5, the loop repeats while the copy of the counter isn't 0
+    let program = lay_out(
+        "5:N1_+:;",
+        &[Range { start: 1, end: 7 }],
+        &LayoutOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(run(program), "5 4 3 2 1 ");
+}
+
+#[test]
+fn test_nested_loops() {
+    let program = lay_out("32:N1_+:~1_+:;", &[2..8, 1..13], &LayoutOptions::default()).unwrap();
+    assert_eq!(run(program), "2 1 2 1 2 1 ");
+}
+
+#[test]
+fn test_locality_keeps_coordinates_short() {
+    let code = format!("{};", "1~".repeat(200));
+    let longest = |locality| {
+        let options = LayoutOptions {
+            locality,
+            ..Default::default()
+        };
+        let program: LanguageState<RhombTiling> = lay_out(&code, &[], &options).unwrap();
+        // Parsing the source maps every character to its tile
+        LanguageState::<RhombTiling>::new_from_string(program.serialize())
+            .unwrap()
+            .get_source_map()
+            .iter()
+            .map(|(tile, _)| tile.len())
+            .max()
+            .unwrap()
+    };
+    assert!(longest(8) < longest(0));
+}
+
+#[test]
+fn test_invalid_loops() {
+    assert_eq!(
+        lay_out::<RhombTiling>(
+            "1~;",
+            &[Range { start: 2, end: 2 }],
+            &LayoutOptions::default()
+        )
+        .err(),
+        Some(LayoutError::InvalidLoop { start: 2, end: 2 })
+    );
+    assert_eq!(
+        lay_out::<RhombTiling>(
+            "1~;",
+            &[Range { start: 0, end: 4 }],
+            &LayoutOptions::default()
+        )
+        .err(),
+        Some(LayoutError::InvalidLoop { start: 0, end: 4 })
+    );
+}
+
+#[test]
+fn test_turns_are_rejected() {
+    let options = LayoutOptions::default();
+    assert_eq!(
+        lay_out::<RhombTiling>("1>2N;", &[], &options).err(),
+        Some(LayoutError::Turn {
+            position: 1,
+            character: '>'
+        })
+    );
+    assert_eq!(
+        lay_out::<RhombTiling>("$5v;", &[], &options).err(),
+        Some(LayoutError::Turn {
+            position: 2,
+            character: 'v'
+        })
+    );
+    // In literals they are just characters
+    let program = lay_out(":`>I'|<'II\"^v\"~;", &[], &options).unwrap();
+    assert_eq!(run(program), "><|");
+}
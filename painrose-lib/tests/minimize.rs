@@ -0,0 +1,83 @@
+mod common;
+
+use painrose_lib::analysis::cfg::AnalysisOptions;
+use painrose_lib::geometry::rhomb::RhombTiling;
+use painrose_lib::language::{LanguageState, RunOutcome};
+use painrose_lib::minimize::{minimize, MinimizeOptions, Mismatch};
+use painrose_lib::translate::befunge::{translate, BefungeOptions};
+
+// `1N` and `2N;` on a straight line, with a `z` between them and an empty tile on both sides of it
+const GAPS: &str = "ACEDDB-w:2N;\n:1N\nEBEDDB:z\n";
+
+fn program(source: &str) -> LanguageState<RhombTiling> {
+    LanguageState::new_from_string(source.to_string()).unwrap()
+}
+
+fn minimized(source: &str) -> String {
+    minimize(&program(source), &[b""], &MinimizeOptions::default())
+        .unwrap()
+        .serialize()
+}
+
+fn run(source: &str, input: &str) -> String {
+    common::run_source(source, input).0
+}
+
+#[test]
+fn test_unreachable_code_is_dropped() {
+    assert_eq!(minimized(":1N;zzz\n"), ":1N;\n");
+    assert_eq!(minimized(":1N;\nCAAC:2N;\n"), ":1N;\n");
+    // The instruction pointer turns before the `N`
+    assert_eq!(minimized(":1>N;\n"), ":1>\n");
+}
+
+#[test]
+fn test_ignored_characters_are_dropped() {
+    assert_eq!(minimized(":1Nzz\n"), ":1N\n");
+    assert_eq!(minimized(GAPS), "ACEDDB-w:2N;\n:1N\n");
+    assert_eq!(run(&minimized(GAPS), ""), "1 2 ");
+}
+
+#[test]
+fn test_characters_in_literals_are_kept() {
+    assert_eq!(minimized(":`zz`II;\n"), ":`zz`II;\n");
+    assert_eq!(minimized(":$5.z N;\n"), ":$5.z N;\n");
+}
+
+#[test]
+fn test_ignored_characters_that_join_lines_are_kept() {
+    assert_eq!(minimized(":1Nz2N;\n"), ":1Nz2N;\n");
+    assert_eq!(minimized(":5.zN;\n"), ":5.zN;\n");
+}
+
+#[test]
+fn test_translated_programs_behave_the_same() {
+    let factorial =
+        translate::<RhombTiling>("&>:1-:v v *_$.@\n ^    _$>\\:^", &BefungeOptions::default())
+            .unwrap()
+            .serialize();
+
+    let inputs: [&[u8]; 3] = [b"1", b"5", b"7"];
+    let result = minimize(&program(&factorial), &inputs, &MinimizeOptions::default())
+        .unwrap()
+        .serialize();
+    assert!(result.len() <= factorial.len());
+    assert_eq!(run(&result, "5"), "120 ");
+}
+
+#[test]
+fn test_mismatches_are_reported() {
+    // Without the `z` the gap is too long for the instruction pointer to cross
+    let options = MinimizeOptions {
+        analysis: AnalysisOptions { max_empty_steps: 1 },
+        ..Default::default()
+    };
+    assert_eq!(
+        minimize(&program(GAPS), &[b""], &options).err(),
+        Some(Mismatch {
+            input: 0,
+            original: Some(RunOutcome::Quit),
+            minimized: Some(RunOutcome::LeftProgram),
+        })
+    );
+}
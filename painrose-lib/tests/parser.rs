@@ -0,0 +1,433 @@
+use painrose_lib::geometry::rhomb::{AbsoluteDirection, RhombTiling, Tile};
+use painrose_lib::geometry::tile_coordinate::{
+    CoordinateParsingErrorKind, CoordinateTraversalError, TileCoordinate,
+};
+use painrose_lib::language::error::{ParseError, ParseErrorKind, ParseWarning};
+use painrose_lib::language::parser::{ParseOptions, ProgramMetadata};
+use painrose_lib::language::source_map::SourceLocation;
+use painrose_lib::language::LanguageState;
+
+fn parse_error(source: &str) -> ParseError<Tile> {
+    LanguageState::<RhombTiling>::new_from_string(source.to_string()).unwrap_err()
+}
+
+#[test]
+fn test_bad_tile_error() {
+    let error = parse_error(":;\nABx-e:\"Hello\"");
+    assert_eq!((error.line, error.column, error.length), (1, 2, 1));
+    match &error.kind {
+        ParseErrorKind::BadCoordinateError(e) => {
+            assert_eq!(e.index, 2);
+            assert_eq!(e.kind, CoordinateParsingErrorKind::ParseError('x'));
+        }
+        kind => panic!("Unexpected error {kind:?}"),
+    }
+    assert_eq!(
+        error.render(":;\nABx-e:\"Hello\""),
+        "error: invalid coordinate: `x` is not a tile\n --> 2:3\n  |\n2 | ABx-e:\"Hello\"\n  |   ^\n"
+    );
+    // The coordinate error is kept as the cause
+    let source = std::error::Error::source(&error).unwrap();
+    assert_eq!(source.to_string(), "`x` is not a tile");
+}
+
+#[test]
+fn test_tile_does_not_fit_error() {
+    let error = parse_error("CAD:;");
+    assert_eq!((error.line, error.column, error.length), (0, 1, 2));
+    match error.kind {
+        ParseErrorKind::BadCoordinateError(e) => assert_eq!(
+            e.kind,
+            CoordinateParsingErrorKind::TraversalError(CoordinateTraversalError {
+                inner_tile: Tile::A,
+                outer_tile: Tile::D
+            })
+        ),
+        kind => panic!("Unexpected error {kind:?}"),
+    }
+}
+
+#[test]
+fn test_bad_direction_error() {
+    let error = parse_error("AE-up:;");
+    assert_eq!((error.line, error.column, error.length), (0, 3, 2));
+    assert_eq!(
+        error.to_string(),
+        "`up` is not a direction at line 1, column 4"
+    );
+}
+
+#[test]
+fn test_missing_prefix_error() {
+    let error = parse_error(":;\n\"Hello\"");
+    assert_eq!((error.line, error.column, error.length), (1, 0, 7));
+    assert!(matches!(error.kind, ParseErrorKind::InvalidPrefixError));
+}
+
+#[test]
+fn test_source_map() {
+    let program =
+        LanguageState::<RhombTiling>::new_from_string("CAE-s:abc\n:xyz".to_string()).unwrap();
+    let source_map = program.get_source_map();
+
+    let start: TileCoordinate<RhombTiling> = "CAE".parse().unwrap();
+    assert_eq!(
+        source_map.get_location(&start),
+        Some(SourceLocation { line: 0, column: 6 })
+    );
+    assert_eq!(
+        source_map.get_tile(SourceLocation { line: 1, column: 1 }),
+        Some(&TileCoordinate::new(vec![]).unwrap())
+    );
+
+    for (tile, location) in source_map.iter() {
+        assert_eq!(source_map.get_tile(*location), Some(tile));
+    }
+    assert_eq!(source_map.iter().count(), 6);
+    assert_eq!(
+        source_map.get_tile(SourceLocation { line: 1, column: 0 }),
+        None
+    );
+}
+
+#[test]
+fn test_collision_warning() {
+    let parsed = LanguageState::<RhombTiling>::parse(":ab\n:c", &ParseOptions::default()).unwrap();
+    assert_eq!(parsed.warnings.len(), 1);
+    let ParseWarning::Collision {
+        location,
+        tile,
+        occupied_by,
+        placed_at,
+    } = &parsed.warnings[0]
+    else {
+        panic!("Unexpected warning {:?}", parsed.warnings[0]);
+    };
+    assert_eq!(*location, SourceLocation { line: 1, column: 1 });
+    assert_eq!(*tile, TileCoordinate::new(vec![]).unwrap());
+    assert_eq!(*occupied_by, Some(SourceLocation { line: 0, column: 1 }));
+
+    // The character moves on past both tiles used by the first line
+    let source_map = parsed.state.get_source_map();
+    assert_eq!(source_map.get_tile(*location), Some(placed_at));
+    assert_ne!(
+        source_map.get_tile(SourceLocation { line: 0, column: 2 }),
+        Some(placed_at)
+    );
+    assert_eq!(
+        parsed.warnings[0].to_string(),
+        "the character at line 2, column 2 was moved because its tile is already used by the character at line 1, column 2"
+    );
+}
+
+#[test]
+fn test_collision_error() {
+    let options = ParseOptions {
+        collisions_are_errors: true,
+        ..ParseOptions::default()
+    };
+    let error = LanguageState::<RhombTiling>::parse(":ab\n:c", &options).unwrap_err();
+    assert_eq!((error.line, error.column, error.length), (1, 1, 1));
+    assert!(matches!(
+        error.kind,
+        ParseErrorKind::CollisionError(Some(SourceLocation { line: 0, column: 1 }))
+    ));
+
+    assert!(LanguageState::<RhombTiling>::parse(":ab\nCAE-s:c", &options).is_ok());
+}
+
+#[test]
+fn test_anchors() {
+    let program =
+        LanguageState::<RhombTiling>::new_from_string(":ab\n@next=+1\n@next:c".to_string())
+            .unwrap();
+    let straight = LanguageState::<RhombTiling>::new_from_string(":abc".to_string()).unwrap();
+    assert_eq!(
+        program
+            .get_source_map()
+            .get_tile(SourceLocation { line: 2, column: 6 }),
+        straight
+            .get_source_map()
+            .get_tile(SourceLocation { line: 0, column: 3 })
+    );
+
+    let program =
+        LanguageState::<RhombTiling>::new_from_string("@start=CAE\n@start-s:;".to_string())
+            .unwrap();
+    let start: TileCoordinate<RhombTiling> = "CAE".parse().unwrap();
+    assert_eq!(
+        program.get_source_map().get_location(&start),
+        Some(SourceLocation { line: 1, column: 9 })
+    );
+}
+
+#[test]
+fn test_anchor_errors() {
+    let error = parse_error(":;\n@loop-e:;");
+    assert_eq!((error.line, error.column, error.length), (1, 0, 5));
+    assert_eq!(
+        error.to_string(),
+        "the anchor `@loop` is not defined at line 2, column 1"
+    );
+
+    let error = parse_error("@a=CAE\n@a=B");
+    assert_eq!((error.line, error.column, error.length), (1, 0, 2));
+    assert!(matches!(error.kind, ParseErrorKind::DuplicateAnchorError(name) if name == "a"));
+
+    let error = parse_error("@a=+1\n:;");
+    assert_eq!((error.line, error.column, error.length), (0, 4, 1));
+    assert!(matches!(
+        error.kind,
+        ParseErrorKind::BadLineReferenceError(_)
+    ));
+
+    let error = parse_error("@a=ABx");
+    assert_eq!((error.line, error.column, error.length), (0, 5, 1));
+}
+
+#[test]
+fn test_relative_prefixes() {
+    let straight = LanguageState::<RhombTiling>::new_from_string(":abcd".to_string()).unwrap();
+    let continued = LanguageState::<RhombTiling>::new_from_string(":ab\n+:cd".to_string()).unwrap();
+    for (line, column) in [(0, 1), (0, 2), (1, 2), (1, 3)] {
+        let straight_column = if line == 0 { column } else { column + 1 };
+        assert_eq!(
+            continued
+                .get_source_map()
+                .get_tile(SourceLocation { line, column }),
+            straight.get_source_map().get_tile(SourceLocation {
+                line: 0,
+                column: straight_column
+            })
+        );
+    }
+
+    // Going east from the start of line 1 enters CAE from the north, so the line heads south
+    let program =
+        LanguageState::<RhombTiling>::new_from_string(":?01/;\n#1/e:N;".to_string()).unwrap();
+    let start: TileCoordinate<RhombTiling> = "CAE".parse().unwrap();
+    assert_eq!(
+        program.get_source_map().get_location(&start),
+        Some(SourceLocation { line: 1, column: 5 })
+    );
+}
+
+#[test]
+fn test_relative_prefix_errors() {
+    let error = parse_error("+:;");
+    assert!(matches!(error.kind, ParseErrorKind::NoPreviousLineError));
+
+    let error = parse_error(":;\n#5:;");
+    assert_eq!((error.line, error.column, error.length), (1, 1, 1));
+    assert!(matches!(
+        error.kind,
+        ParseErrorKind::BadLineReferenceError(_)
+    ));
+
+    let error = parse_error(":;\n#1/nx:;");
+    assert_eq!((error.line, error.column, error.length), (1, 4, 1));
+    assert!(matches!(error.kind, ParseErrorKind::BadDirectionError(d) if d == "x"));
+}
+
+#[test]
+fn test_layout_directives() {
+    let program =
+        LanguageState::<RhombTiling>::new_from_string("!version 2\n:a@eb@@".to_string()).unwrap();
+    let source_map = program.get_source_map();
+    let east: TileCoordinate<RhombTiling> = "CAE".parse().unwrap();
+    assert_eq!(
+        source_map.get_tile(SourceLocation { line: 1, column: 4 }),
+        Some(&east)
+    );
+    // Directives don't take up a tile, `@@` places a single `@`
+    assert_eq!(source_map.iter().count(), 3);
+    assert_eq!(
+        source_map.get_tile(SourceLocation { line: 1, column: 5 }),
+        east.go(AbsoluteDirection::South)
+            .ok()
+            .map(|(tile, _)| tile)
+            .as_ref()
+    );
+
+    // Turning right from north heads east, the same as `@e`
+    let turned =
+        LanguageState::<RhombTiling>::new_from_string("!version 2\n:a@>b".to_string()).unwrap();
+    assert_eq!(
+        turned
+            .get_source_map()
+            .get_tile(SourceLocation { line: 1, column: 4 }),
+        Some(&east)
+    );
+
+    // Older programs keep `@` as a character
+    let old = LanguageState::<RhombTiling>::new_from_string(":a@eb".to_string()).unwrap();
+    assert_eq!(old.get_source_map().iter().count(), 4);
+    assert_eq!(old.serialize(), ":a@eb\n");
+}
+
+#[test]
+fn test_bad_directive_error() {
+    let error = parse_error("!version 2\n:a@xb");
+    assert_eq!((error.line, error.column, error.length), (1, 2, 2));
+    assert_eq!(
+        error.to_string(),
+        "`@x` is not a layout directive at line 2, column 3"
+    );
+
+    let error = parse_error("!version 2\n:a@");
+    assert_eq!((error.line, error.column, error.length), (1, 2, 1));
+
+    let error = parse_error(":a\n!version 2");
+    assert_eq!((error.line, error.column, error.length), (1, 0, 8));
+    assert!(matches!(error.kind, ParseErrorKind::LateVersionError));
+}
+
+#[test]
+fn test_metadata_headers() {
+    let code = "!title Hello World \n!author someone\n!tiling rhomb\n!version 1\n// The program\n:\"Hi\"I;";
+    let parsed = LanguageState::<RhombTiling>::parse(code, &ParseOptions::default()).unwrap();
+    assert_eq!(
+        parsed.metadata,
+        ProgramMetadata {
+            title: Some("Hello World".to_string()),
+            author: Some("someone".to_string()),
+            tiling: Some("rhomb".to_string()),
+            entry: None,
+            version: Some(1),
+        }
+    );
+    assert_eq!(
+        parsed
+            .state
+            .get_source_map()
+            .get_tile(SourceLocation { line: 5, column: 1 }),
+        Some(&TileCoordinate::new(vec![]).unwrap())
+    );
+}
+
+#[test]
+fn test_header_errors() {
+    let error = parse_error("!name x");
+    assert_eq!((error.line, error.column, error.length), (0, 0, 5));
+    assert!(matches!(error.kind, ParseErrorKind::UnknownHeaderError(h) if h == "name"));
+
+    let error = parse_error("!title a\n!title b");
+    assert!(matches!(error.kind, ParseErrorKind::DuplicateHeaderError(h) if h == "title"));
+
+    let error = parse_error("!tiling  square");
+    assert_eq!((error.line, error.column, error.length), (0, 9, 6));
+    assert_eq!(
+        error.to_string(),
+        "the program is written for the `square` tiling but runs on the `rhomb` tiling at line 1, column 10"
+    );
+
+    let error = parse_error("!version one");
+    assert!(matches!(error.kind, ParseErrorKind::BadHeaderValueError(_)));
+    let error = parse_error("!version 3");
+    assert!(matches!(
+        error.kind,
+        ParseErrorKind::UnsupportedVersionError(3)
+    ));
+
+    let error = parse_error("!entry ABx\n:;");
+    assert_eq!((error.line, error.column, error.length), (0, 9, 1));
+}
+
+fn include_options() -> ParseOptions {
+    ParseOptions {
+        base_directory: Some(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/programs").into()),
+        ..ParseOptions::default()
+    }
+}
+
+#[test]
+fn test_include() {
+    let parsed =
+        LanguageState::<RhombTiling>::parse("!include print_two.pr 4 C\n:;", &include_options())
+            .unwrap();
+    let state = parsed.state;
+    let mounted: TileCoordinate<RhombTiling> = "CEDBC".parse().unwrap();
+    assert_eq!(
+        TileCoordinate::new(vec![]).unwrap().mount(4, &[Tile::C]),
+        Ok(mounted.clone())
+    );
+    // Included tiles lead back to the `!include` header
+    let header = SourceLocation { line: 0, column: 9 };
+    assert_eq!(state.get_source_map().get_location(&mounted), Some(header));
+    assert_eq!(state.get_source_map().iter().count(), 4);
+    assert_eq!(state.get_source_map().get_tile(header), None);
+}
+
+#[test]
+fn test_include_errors() {
+    let include_error =
+        |code: &str| LanguageState::<RhombTiling>::parse(code, &include_options()).unwrap_err();
+
+    let error = include_error("!include print_one.pr 2 C");
+    assert_eq!((error.line, error.column, error.length), (0, 9, 16));
+    assert!(matches!(
+        error.kind,
+        ParseErrorKind::IncludeTooLargeError(2)
+    ));
+
+    let twice = "!include print_two.pr 4 C\n!include print_two.pr 4 C";
+    let options = ParseOptions {
+        collisions_are_errors: true,
+        ..include_options()
+    };
+    let error = LanguageState::<RhombTiling>::parse(twice, &options).unwrap_err();
+    assert_eq!(error.line, 1);
+    assert!(matches!(
+        error.kind,
+        ParseErrorKind::CollisionError(Some(SourceLocation { line: 0, column: 9 }))
+    ));
+    // Without the option the characters of the second copy are left out
+    let parsed = LanguageState::<RhombTiling>::parse(twice, &include_options()).unwrap();
+    assert_eq!(parsed.warnings.len(), 3);
+    assert_eq!(
+        parsed.warnings[0].to_string(),
+        "the file included at line 2, column 10 has a character that was left out because its tile is already used by the character at line 1, column 10"
+    );
+
+    // Warnings of the included file are passed on
+    let parsed =
+        LanguageState::<RhombTiling>::parse("!include collide.pr 6 C", &include_options()).unwrap();
+    assert_eq!(parsed.warnings.len(), 1);
+    let ParseWarning::IncludedFileWarning {
+        location,
+        path,
+        warning,
+    } = &parsed.warnings[0]
+    else {
+        panic!("Unexpected warning {:?}", parsed.warnings[0]);
+    };
+    assert_eq!(*location, SourceLocation { line: 0, column: 9 });
+    assert_eq!(path, "collide.pr");
+    assert!(matches!(**warning, ParseWarning::Collision { .. }));
+    assert_eq!(
+        parsed.warnings[0].to_string(),
+        "in the file `collide.pr` included at line 1, column 10: the character at line 2, column 2 was moved because its tile is already used by the character at line 1, column 2"
+    );
+
+    let error = include_error("!include print_two.pr 4 D");
+    assert!(matches!(error.kind, ParseErrorKind::TraversalError(_)));
+
+    let error = include_error("!include include_self.pr 4 C");
+    assert_eq!(
+        error.to_string(),
+        "`include_self.pr` is included by itself at line 1, column 10"
+    );
+
+    let error = include_error("!include bad_tile.pr 4 C");
+    assert_eq!(
+        error.to_string(),
+        "invalid coordinate: `x` is not a tile in the included file `bad_tile.pr`, line 1, column 3 at line 1, column 10"
+    );
+    assert!(std::error::Error::source(&error).is_none());
+
+    let error = include_error("!include missing.pr 4 C");
+    assert!(matches!(error.kind, ParseErrorKind::IncludeError(_)));
+
+    let error = include_error("!include print_two.pr C");
+    assert!(matches!(error.kind, ParseErrorKind::BadHeaderValueError(_)));
+}
@@ -0,0 +1,63 @@
+use painrose_lib::geometry::rhomb::RhombTiling;
+use painrose_lib::language::LanguageState;
+
+fn parse(code: &str) -> LanguageState<RhombTiling> {
+    LanguageState::<RhombTiling>::new_from_string(code.to_string()).unwrap()
+}
+
+fn assert_same_code(a: &LanguageState<RhombTiling>, b: &LanguageState<RhombTiling>) {
+    let tiles = |state: &LanguageState<RhombTiling>| {
+        let mut tiles: Vec<_> = state
+            .get_source_map()
+            .iter()
+            .map(|(tile, _)| (format!("{tile}"), state.get_char(tile)))
+            .collect();
+        tiles.sort();
+        tiles
+    };
+    assert_eq!(tiles(a), tiles(b));
+}
+
+#[test]
+fn test_straight_line() {
+    assert_eq!(
+        parse(":\"Hello World!\"I;").serialize(),
+        ":\"Hello World!\"I;\n"
+    );
+}
+
+#[test]
+fn test_round_trip() {
+    for code in [
+        ":?01/;\nCAE-s:N;",
+        "!version 2\n:ab@ec@<de@@f\n+:gh\n#2/ss-w:ij",
+        ":a@b@@c",
+        "CAE-s:abc\n:xyz\nAE-w:1234567",
+        ":abc\n:de",
+    ] {
+        let original = parse(code);
+        let serialized = original.serialize();
+        let parsed = parse(&serialized);
+
+        assert_same_code(&original, &parsed);
+        assert_eq!(parsed.serialize(), serialized);
+    }
+}
+
+#[test]
+fn test_entry_point() {
+    let original = parse("!entry CAE-s\nCAE-s:1N;");
+    let serialized = original.serialize();
+    assert_eq!(serialized, "!entry CAE-s\nCAE-s:1N;\n");
+}
+
+#[test]
+fn test_entry_point_after_running() {
+    let mut program = parse(":1N;");
+    let mut output = vec![];
+    while program.is_running() {
+        program.step(&mut output, &mut &b""[..]);
+    }
+    assert_eq!(program.serialize(), ":1N;\n");
+    assert!(program.to_json().find("entry").is_none());
+}
@@ -0,0 +1,166 @@
+//! Embeds Painrose programs in Rust code. The programs are parsed while the crate is built, so
+//! parse errors become compiler errors, and each macro expands to a function
+//! `fn<Out: Write, In: Read>(&mut Out, &mut In) -> RunOutcome` that runs the program.
+//!
+//! The expanded code uses `painrose_lib`, so crates using these macros need to depend on it too.
+use painrose_lib::geometry::rhomb::RhombTiling;
+use painrose_lib::language::parser::ParseOptions;
+use painrose_lib::language::LanguageState;
+use proc_macro::TokenStream;
+use quote::quote;
+
+/// The part of a string literal with the characters from `start` to `end` of its value. Only
+/// works for literals without escapes and on compilers that support spans inside literals.
+fn literal_span(literal: &syn::LitStr, start: usize, end: usize) -> Option<proc_macro2::Span> {
+    let token = literal.token().to_string();
+    let quote = token.find('"')?;
+    let hashes = quote - token.find('r').map_or(quote, |r| r + 1);
+    let content = token.get(quote + 1..token.len() - 1 - hashes)?;
+    if content != literal.value() {
+        return None;
+    }
+
+    let byte = |index| {
+        content
+            .char_indices()
+            .nth(index)
+            .map_or(content.len(), |(byte, _)| byte)
+    };
+    literal
+        .token()
+        .subspan(quote + 1 + byte(start)..quote + 1 + byte(end))
+}
+
+/// Parses the program and expands to the function running it. The program is embedded in the
+/// canonical form of [`LanguageState::serialize`], so anchors, relative prefixes and included
+/// files are already resolved, and it is parsed and compiled once the first time it runs.
+///
+/// Errors point at `literal` if the source is its value, otherwise at `span`.
+fn expand(
+    source: &str,
+    options: &ParseOptions,
+    literal: Option<&syn::LitStr>,
+    span: proc_macro2::Span,
+) -> syn::Result<proc_macro2::TokenStream> {
+    // The compiler already starts the message with `error:`, the snippet shows where in the
+    // program the error is
+    let parsed = LanguageState::<RhombTiling>::parse(source, options).map_err(|error| {
+        let rendered = error.render(source);
+        let start = source
+            .lines()
+            .take(error.line)
+            .map(|line| line.chars().count() + 1)
+            .sum::<usize>()
+            + error.column;
+        let span = literal
+            .and_then(|literal| literal_span(literal, start, start + error.length.max(1)))
+            .unwrap_or(span);
+        syn::Error::new(span, rendered.trim_start_matches("error: ").trim_end())
+    })?;
+
+    // Characters the source can't hold, like line breaks, would be lost
+    let canonical = parsed.state.serialize();
+    if !LanguageState::<RhombTiling>::new_from_string(canonical.clone())
+        .is_ok_and(|embedded| embedded.to_json() == parsed.state.to_json())
+    {
+        return Err(syn::Error::new(
+            span,
+            "the program has characters that can't be embedded, like line breaks",
+        ));
+    }
+
+    Ok(quote! {
+        {
+            fn program<Out: ::std::io::Write, In: ::std::io::Read>(
+                out: &mut Out,
+                input: &mut In,
+            ) -> ::painrose_lib::language::RunOutcome {
+                static PROGRAM: ::std::sync::OnceLock<
+                    ::painrose_lib::language::LanguageState<
+                        ::painrose_lib::geometry::rhomb::RhombTiling,
+                    >,
+                > = ::std::sync::OnceLock::new();
+
+                let mut state = PROGRAM
+                    .get_or_init(|| {
+                        let mut state = ::painrose_lib::language::LanguageState::new_from_string(
+                            #canonical.to_string(),
+                        )
+                        .expect("the program was checked when it was compiled");
+                        state.compile();
+                        state
+                    })
+                    .clone();
+                while state.is_running() {
+                    state.step(out, input);
+                }
+                state.get_outcome().unwrap()
+            }
+            program
+        }
+    })
+}
+
+/// Expands to a function running the program in the string literal
+///
+/// ```ignore
+/// let hello = painrose_macros::painrose!{ ":\"Hello World!\"I;" };
+/// hello(&mut std::io::stdout(), &mut std::io::stdin());
+/// ```
+#[proc_macro]
+pub fn painrose(input: TokenStream) -> TokenStream {
+    let source = syn::parse_macro_input!(input as syn::LitStr);
+
+    expand(
+        &source.value(),
+        &ParseOptions::default(),
+        Some(&source),
+        source.span(),
+    )
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
+}
+
+/// Expands to a function running the program in a file, relative to the directory of the
+/// crate's `Cargo.toml`. `!include` headers are relative to the file.
+///
+/// ```ignore
+/// let program = painrose_macros::painrose_file!("programs/hello.pr");
+/// ```
+#[proc_macro]
+pub fn painrose_file(input: TokenStream) -> TokenStream {
+    let path = syn::parse_macro_input!(input as syn::LitStr);
+    let full_path = std::path::Path::new(&std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default())
+        .join(path.value());
+
+    let source = match std::fs::read_to_string(&full_path) {
+        Ok(source) => source,
+        Err(error) => {
+            return syn::Error::new(
+                path.span(),
+                format!("could not read `{}`: {error}", full_path.display()),
+            )
+            .into_compile_error()
+            .into()
+        }
+    };
+    let options = ParseOptions {
+        base_directory: full_path.parent().map(Into::into),
+        ..Default::default()
+    };
+
+    match expand(&source, &options, None, path.span()) {
+        Ok(program) => {
+            // Makes cargo rebuild the crate when the file changes
+            let full_path = full_path.display().to_string();
+            quote! {
+                {
+                    const _: &str = include_str!(#full_path);
+                    #program
+                }
+            }
+            .into()
+        }
+        Err(error) => error.into_compile_error().into(),
+    }
+}
@@ -0,0 +1,3 @@
+fn main() {
+    let _ = painrose_macros::painrose! { ":1N;\nAB-q:2N;" };
+}
@@ -0,0 +1,38 @@
+use painrose_lib::language::RunOutcome;
+use painrose_macros::{painrose, painrose_file};
+
+#[test]
+fn test_inline_program() {
+    let hello = painrose! { ":\"Hello World!\"I;" };
+
+    let mut output = vec![];
+    assert_eq!(hello(&mut output, &mut std::io::empty()), RunOutcome::Quit);
+    assert_eq!(output, b"Hello World!");
+}
+
+#[test]
+fn test_program_reads_input() {
+    let add = painrose! { ":nn+N;" };
+
+    let mut output = vec![];
+    add(&mut output, &mut "2 3".as_bytes());
+    assert_eq!(output, b"5 ");
+}
+
+#[test]
+fn test_file_program() {
+    // Includes `print_two.pr` from the same directory
+    let program = painrose_file!("tests/programs/includes.pr");
+
+    let mut output = vec![];
+    assert_eq!(
+        program(&mut output, &mut std::io::empty()),
+        RunOutcome::Quit
+    );
+    assert_eq!(output, b"2 ");
+}
+
+#[test]
+fn test_bad_programs_fail_to_compile() {
+    trybuild::TestCases::new().compile_fail("tests/compile_fail/*.rs");
+}